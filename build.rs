@@ -9,6 +9,9 @@ const FILES: &'static [&'static str] = &[
     "src/data/player.rs.in",
     "src/data/map.rs.in",
     "src/data/monster.rs.in",
+    "src/data/scripts.rs.in",
+    "src/data/skills.rs.in",
+    "src/data/recording.rs.in",
 ];
 pub fn main() {
     let out_dir = env::var_os("OUT_DIR").unwrap();
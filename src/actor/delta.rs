@@ -0,0 +1,177 @@
+// Delta compression of the state updates sent to a client
+//
+// The instance generates a Position notification for every entity at every tick, but a client
+// only needs the ones that differ from the last state it has received. The state of each
+// entity in the last update sent is kept, and the updates of idle entities are omitted. The
+// connection is reliable, so an update that has been sent counts as acknowledged.
+//
+// An entity the client has no state for gets a complete Position notification. Afterwards, a
+// StateUpdate notification only carries the fields that changed among the position, the speed
+// and the pv. Every KEYFRAME_PERIOD ticks, complete updates are sent anyway, so that a client
+// always ends up with the right state
+use std::collections::HashMap;
+
+use nalgebra::{Point2,Vector2};
+
+use messages::Notification;
+
+pub static KEYFRAME_PERIOD: u64 = 100;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+struct EntitySnapshot {
+    position: Point2<f32>,
+    speed: Vector2<f32>,
+    pv: u64,
+}
+
+#[derive(Debug)]
+pub struct StateUpdates {
+    acknowledged: HashMap<u64,EntitySnapshot>,
+    // Number of ticks before the next keyframe
+    until_keyframe: u64,
+}
+
+impl StateUpdates {
+    /// The offset spreads the keyframes of the clients over several ticks
+    pub fn new(offset: u64) -> StateUpdates {
+        StateUpdates {
+            acknowledged: HashMap::new(),
+            until_keyframe: offset % KEYFRAME_PERIOD,
+        }
+    }
+
+    /// Removes the unneeded state updates from the notifications of a tick, and reduces the
+    /// others to the fields that changed
+    pub fn compress(&mut self, notifications: Vec<Notification>) -> Vec<Notification> {
+        let keyframe = self.until_keyframe == 0;
+        self.until_keyframe = if keyframe {
+            KEYFRAME_PERIOD - 1
+        } else {
+            self.until_keyframe - 1
+        };
+        let acknowledged = &mut self.acknowledged;
+        notifications.into_iter().filter_map(|notification| {
+            match notification {
+                Notification::Position { entity, position, speed, pv } => {
+                    let snapshot = EntitySnapshot {
+                        position: position,
+                        speed: speed,
+                        pv: pv,
+                    };
+                    let previous = acknowledged.insert(entity, snapshot);
+                    match previous {
+                        Some(previous) if !keyframe => partial_update(entity, &previous, &snapshot),
+                        _ => Some(Notification::position(entity, position, speed, pv)),
+                    }
+                }
+                // The client has to get the full state of an entity that appears again
+                Notification::NewEntity { entity, .. } |
+                Notification::EntityHasQuit { entity } => {
+                    acknowledged.remove(&entity);
+                    Some(notification)
+                }
+                _ => Some(notification),
+            }
+        }).collect()
+    }
+}
+
+// None if nothing changed
+fn partial_update(entity: u64, previous: &EntitySnapshot, current: &EntitySnapshot) -> Option<Notification> {
+    if previous == current {
+        return None;
+    }
+    let position = if previous.position != current.position { Some(current.position) } else { None };
+    let speed = if previous.speed != current.speed { Some(current.speed) } else { None };
+    let pv = if previous.pv != current.pv { Some(current.pv) } else { None };
+    Some(Notification::StateUpdate {
+        entity: entity,
+        position: position,
+        speed: speed,
+        pv: pv,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::{Point2,Vector2};
+
+    use messages::Notification;
+    use super::{StateUpdates,KEYFRAME_PERIOD};
+
+    fn position(entity: u64, x: f32, pv: u64) -> Notification {
+        Notification::position(entity, Point2::new(x, 0.0), Vector2::new(0.0, 0.0), pv)
+    }
+
+    fn updated(notifications: &[Notification]) -> Vec<u64> {
+        notifications.iter().filter_map(|n| match *n {
+            Notification::Position { entity, .. } |
+            Notification::StateUpdate { entity, .. } => Some(entity),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn idle_entities_are_omitted() {
+        let mut updates = StateUpdates::new(1);
+        let sent = updates.compress(vec![position(1, 0.0, 100), position(2, 0.0, 100)]);
+        assert_eq!(updated(&sent), vec![1, 2]);
+
+        let sent = updates.compress(vec![position(1, 0.0, 100), position(2, 1.0, 100)]);
+        assert_eq!(updated(&sent), vec![2]);
+
+        let sent = updates.compress(vec![position(1, 0.0, 90), position(2, 1.0, 100)]);
+        assert_eq!(updated(&sent), vec![1]);
+
+        // The other notifications are untouched
+        let say = Notification::say(1, String::from("hello"));
+        let sent = updates.compress(vec![position(1, 0.0, 90), say]);
+        assert_eq!(sent.len(), 1);
+        assert!(updated(&sent).is_empty());
+    }
+
+    #[test]
+    fn only_changed_fields() {
+        let mut updates = StateUpdates::new(1);
+        let sent = updates.compress(vec![position(1, 0.0, 100)]);
+        match sent[0] {
+            Notification::Position { entity: 1, pv: 100, .. } => {}
+            _ => panic!("The first update should be complete"),
+        }
+
+        let sent = updates.compress(vec![position(1, 0.0, 90)]);
+        match sent[0] {
+            Notification::StateUpdate { entity: 1, position: None, speed: None, pv: Some(90) } => {}
+            _ => panic!("Only the pv should be sent"),
+        }
+
+        let sent = updates.compress(vec![position(1, 2.0, 90)]);
+        match sent[0] {
+            Notification::StateUpdate { entity: 1, position: Some(position), speed: None, pv: None } => {
+                assert_eq!(position, Point2::new(2.0, 0.0));
+            }
+            _ => panic!("Only the position should be sent"),
+        }
+    }
+
+    #[test]
+    fn keyframes() {
+        let mut updates = StateUpdates::new(1);
+        let mut sent_ticks = Vec::new();
+        for tick in 0..(2 * KEYFRAME_PERIOD + 2) {
+            if !updates.compress(vec![position(1, 0.0, 100)]).is_empty() {
+                sent_ticks.push(tick);
+            }
+        }
+        assert_eq!(sent_ticks, vec![0, 1, KEYFRAME_PERIOD + 1, 2 * KEYFRAME_PERIOD + 1]);
+    }
+
+    #[test]
+    fn reappearing_entity_gets_full_state() {
+        let mut updates = StateUpdates::new(1);
+        updates.compress(vec![position(1, 0.0, 100)]);
+        updates.compress(vec![Notification::entity_has_quit(1)]);
+        let sent = updates.compress(vec![position(1, 0.0, 100)]);
+        assert_eq!(updated(&sent), vec![1]);
+    }
+}
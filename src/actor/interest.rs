@@ -0,0 +1,228 @@
+// Area of interest of a client
+//
+// A client is only told about the entities around its own entities. An entity becomes visible
+// when it comes closer than VIEW_RADIUS to one of them, and is forgotten when it goes further
+// than VIEW_RADIUS + HYSTERESIS, so that an entity walking along the boundary does not appear
+// and disappear every tick. The client learns about these changes with NewEntity and
+// EntityHasQuit notifications, the ones generated by the instance are not forwarded
+use std::collections::HashSet;
+
+use nalgebra::{Point2,FloatPoint};
+
+use id::Id;
+use entity::{Entity,EntityStore};
+use messages::Notification;
+
+pub static VIEW_RADIUS: f32 = 40.0;
+pub static HYSTERESIS: f32 = 5.0;
+
+#[derive(Debug,Default)]
+pub struct Interest {
+    // Entities the client knows about
+    visible: HashSet<u64>,
+    // Projectiles the client has been told about
+    projectiles: HashSet<u64>,
+}
+
+impl Interest {
+    pub fn new() -> Interest {
+        Default::default()
+    }
+
+    /// Updates the visible entities, and returns the notifications the client must receive
+    ///
+    /// The notifications about entities that have just become visible come first, then the
+    /// notifications of the tick about visible entities, then the ones about the entities
+    /// that are not visible any more
+    pub fn filter(&mut self,
+                  owned: &HashSet<Id<Entity>>,
+                  entities: &EntityStore,
+                  notifications: &[Notification],
+                  ) -> Vec<Notification> {
+        let viewers: Vec<Point2<f32>> = owned.iter()
+            .filter_map(|id| entities.get(*id))
+            .map(|e| e.get_position())
+            .collect();
+        let enter_sqdistance = VIEW_RADIUS * VIEW_RADIUS;
+        let leave_sqdistance = (VIEW_RADIUS + HYSTERESIS) * (VIEW_RADIUS + HYSTERESIS);
+
+        let mut filtered = Vec::new();
+        let mut now_visible = HashSet::new();
+        for entity in entities.iter() {
+            let id = entity.get_id().as_u64();
+            let position = entity.get_position();
+            let sqdistance = if self.visible.contains(&id) {
+                leave_sqdistance
+            } else {
+                enter_sqdistance
+            };
+            let visible = owned.contains(&entity.get_id()) ||
+                viewers.iter().any(|v| v.distance_squared(&position) <= sqdistance);
+            if !visible {
+                continue;
+            }
+            if !self.visible.contains(&id) {
+                filtered.push(Notification::new_entity(id, position, entity.get_skin(), entity.get_pv()));
+            }
+            now_visible.insert(id);
+        }
+
+        // The entities that left during the tick are still known until the end of it, so that
+        // their last notifications (a death for instance) are received
+        for notification in notifications {
+            if self.is_relevant(notification, &now_visible) {
+                filtered.push(notification.clone());
+            }
+        }
+
+        let mut quitting: Vec<u64> = self.visible.difference(&now_visible).cloned().collect();
+        quitting.sort();
+        for id in quitting {
+            filtered.push(Notification::entity_has_quit(id));
+        }
+        self.visible = now_visible;
+        filtered
+    }
+
+    fn is_relevant(&mut self, notification: &Notification, now_visible: &HashSet<u64>) -> bool {
+        let visible = &self.visible;
+        let projectiles = &mut self.projectiles;
+        let known = |id: &u64| visible.contains(id) || now_visible.contains(id);
+        match *notification {
+            // Generated by filter
+            Notification::NewEntity { .. } | Notification::EntityHasQuit { .. } => false,
+            Notification::ThisIsYou { .. } => true,
+            Notification::Walk { entity, .. } |
+            Notification::Say { entity, .. } |
+            Notification::Position { entity, .. } |
+            Notification::StateUpdate { entity, .. } |
+            Notification::Death { entity } |
+            Notification::CastStarted { entity, .. } |
+            Notification::CastInterrupted { entity, .. } => known(&entity),
+            Notification::Damage { source, victim, .. } => known(&source) || known(&victim),
+            Notification::ProjectileSpawned { projectile, owner, .. } => {
+                let relevant = known(&owner);
+                if relevant {
+                    projectiles.insert(projectile);
+                }
+                relevant
+            }
+            Notification::ProjectileDespawned { projectile } => projectiles.remove(&projectile),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use entity::Entity;
+    use messages::Notification;
+    use tests::fixtures::{self,store};
+    use super::{Interest,VIEW_RADIUS,HYSTERESIS};
+
+    fn monster(x: f32) -> Entity {
+        fixtures::monster(x, 0.0)
+    }
+
+    // The same entity, at another position
+    fn moved(entity: &Entity, x: f32) -> Entity {
+        let mut moved = monster(x);
+        moved.restore_identity(entity.get_id(), entity.get_skin());
+        moved
+    }
+
+    fn new_entities(notifications: &[Notification]) -> Vec<u64> {
+        notifications.iter().filter_map(|n| match *n {
+            Notification::NewEntity { entity, .. } => Some(entity),
+            _ => None,
+        }).collect()
+    }
+
+    fn quitting(notifications: &[Notification]) -> Vec<u64> {
+        notifications.iter().filter_map(|n| match *n {
+            Notification::EntityHasQuit { entity } => Some(entity),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn only_close_entities_are_visible() {
+        let me = monster(0.0);
+        let close = monster(VIEW_RADIUS - 1.0);
+        let far = monster(VIEW_RADIUS + 1.0);
+        let (me_id, close_id, far_id) = (me.get_id(), close.get_id(), far.get_id());
+        let mut owned = HashSet::new();
+        owned.insert(me_id);
+        let entities = store(vec![me, close, far]);
+        let notifications = vec![
+            Notification::say(close_id.as_u64(), String::from("close")),
+            Notification::say(far_id.as_u64(), String::from("far")),
+        ];
+
+        let mut interest = Interest::new();
+        let filtered = interest.filter(&owned, &entities, &notifications);
+        let mut new = new_entities(&filtered);
+        new.sort();
+        let mut expected = vec![me_id.as_u64(), close_id.as_u64()];
+        expected.sort();
+        assert_eq!(new, expected);
+        assert!(filtered.iter().any(|n| match *n {
+            Notification::Say { entity, .. } => entity == close_id.as_u64(),
+            _ => false,
+        }));
+        assert!(!filtered.iter().any(|n| match *n {
+            Notification::Say { entity, .. } => entity == far_id.as_u64(),
+            _ => false,
+        }));
+
+        // Already known entities are not sent again
+        let filtered = interest.filter(&owned, &entities, &[]);
+        assert!(filtered.is_empty());
+    }
+
+    #[test]
+    fn hysteresis() {
+        let me = monster(0.0);
+        let other = monster(VIEW_RADIUS - 1.0);
+        let (me_id, other_id) = (me.get_id(), other.get_id());
+        let mut owned = HashSet::new();
+        owned.insert(me_id);
+        let mut interest = Interest::new();
+        interest.filter(&owned, &store(vec![moved(&me, 0.0), moved(&other, VIEW_RADIUS - 1.0)]), &[]);
+
+        // Between the two radiuses, the entity stays visible
+        let entities = store(vec![moved(&me, 0.0), moved(&other, VIEW_RADIUS + HYSTERESIS / 2.0)]);
+        assert!(interest.filter(&owned, &entities, &[]).is_empty());
+
+        let entities = store(vec![moved(&me, 0.0), moved(&other, VIEW_RADIUS + HYSTERESIS + 1.0)]);
+        assert_eq!(quitting(&interest.filter(&owned, &entities, &[])), vec![other_id.as_u64()]);
+
+        // And it does not come back before being closer than the view radius
+        let entities = store(vec![moved(&me, 0.0), moved(&other, VIEW_RADIUS + HYSTERESIS / 2.0)]);
+        assert!(interest.filter(&owned, &entities, &[]).is_empty());
+
+        let entities = store(vec![moved(&me, 0.0), moved(&other, VIEW_RADIUS - 1.0)]);
+        assert_eq!(new_entities(&interest.filter(&owned, &entities, &[])), vec![other_id.as_u64()]);
+    }
+
+    #[test]
+    fn removed_entity_quits_after_its_death() {
+        let me = monster(0.0);
+        let other = monster(1.0);
+        let (me_id, other_id) = (me.get_id(), other.get_id());
+        let mut owned = HashSet::new();
+        owned.insert(me_id);
+        let mut interest = Interest::new();
+        interest.filter(&owned, &store(vec![moved(&me, 0.0), moved(&other, 1.0)]), &[]);
+
+        let notifications = vec![Notification::Death { entity: other_id.as_u64() }];
+        let filtered = interest.filter(&owned, &store(vec![me]), &notifications);
+        assert_eq!(filtered.len(), 2);
+        match filtered[0] {
+            Notification::Death { entity } => assert_eq!(entity, other_id.as_u64()),
+            ref other => panic!("unexpected notification {:?}", other),
+        }
+        assert_eq!(quitting(&filtered), vec![other_id.as_u64()]);
+    }
+}
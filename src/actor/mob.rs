@@ -1,29 +1,59 @@
 use std::collections::HashSet;
 use std::fmt::{self,Debug,Formatter};
 
+use nalgebra::Point2;
+
 use behaviour_tree::tree::BehaviourTreeNode;
 use id::Id;
 use actor::ActorId;
 use entity::{Entity,EntityStore};
 use messages::{self,Command,Notification,EntityOrder};
 use scripts::{BehaviourTree};
-use ai::{BehaviourTreeData,Context};
+use ai::{self,BehaviourTreeData,Context};
+use data::{AiDebug,NodeStatus};
+use instance::InstanceRng;
+use instance::geometry::MapGeometry;
+
+// By default, behaviour trees are evaluated every other tick
+static DEFAULT_THINK_PERIOD: u64 = 2;
+// An AI with no player closer than that stops evaluating its behaviour tree
+static WAKE_UP_SQDISTANCE: f32 = 2500.0;
 
 pub struct AiActor {
     id: ActorId,
     entity: Option<Id<Entity>>,
     entities: HashSet<Id<Entity>>, // XXX: Do we really need this?
+    tree_name: String,
     tree: BehaviourTree,
     tree_data: BehaviourTreeData,
+    // Result of the last evaluation of the tree
+    last_status: Option<NodeStatus>,
+    // Number of ticks between two evaluations of the tree
+    think_period: u64,
+    // Staggers the evaluations of all AIs across ticks
+    think_offset: u64,
+    asleep: bool,
+}
+
+/// What an AI did during a tick
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ThinkOutcome {
+    Evaluated,
+    // Not its turn to think
+    Skipped,
+    // No player nearby
+    Asleep,
 }
 
 impl Debug for AiActor {
     fn fmt(&self, f: &mut Formatter) -> Result<(),fmt::Error> {
-        let tree = "[behaviour tree]";
         f.debug_struct("AiActor")
             .field("id", &self.id)
             .field("entities", &self.entities)
-            .field("tree", &tree)
+            .field("tree", &self.tree_name)
+            .field("last_status", &self.last_status)
+            .field("think_period", &self.think_period)
+            .field("asleep", &self.asleep)
             .finish()
     }
 }
@@ -36,31 +66,120 @@ impl AiActor {
     }
     pub fn execute_orders(&mut self,
                       entities: &mut EntityStore,
+                      geometry: &MapGeometry,
                       _notifications: &mut Vec<Notification>,
-                      _previous: &[Notification]) {
+                      previous: &[Notification],
+                      rng: &mut InstanceRng,
+                      tick: u64) -> ThinkOutcome {
         // Context should give access to storage / current game state
         let me = match self.entity {
             None => {
                 warn!("Trying to execute behaviour tree on AI without main entity {}", self.id);
-                return;
+                return ThinkOutcome::Skipped;
             }
             Some(me) => me,
         };
-        let mut context = Context::new(me, entities, &mut self.tree_data);
-        self.tree.visit(&mut context);
+        // Perception accumulates until the next evaluation, so no notification is missed
+        self.tree_data.perceive(me, entities, previous);
+
+        if (tick + self.think_offset) % self.think_period != 0 {
+            return ThinkOutcome::Skipped;
+        }
+        if !player_nearby(me, entities) {
+            if !self.asleep {
+                debug!("AI {} falling asleep", self.id);
+                self.asleep = true;
+                if let Some(entity) = entities.get_mut(me) {
+                    let _ = entity.walk(None);
+                }
+            }
+            self.tree_data.forget_perception();
+            return ThinkOutcome::Asleep;
+        }
+        self.asleep = false;
+
+        self.tree_data.clear_trace();
+        let result = {
+            let mut context = Context::new(me, entities, geometry, &mut self.tree_data, rng);
+            self.tree.visit(&mut context)
+        };
+        self.last_status = Some(ai::to_status(&result));
+        self.tree_data.forget_perception();
+        ThinkOutcome::Evaluated
     }
     pub fn register_entity(&mut self, entity: Id<Entity>) {
         self.entity = Some(entity);
         self.entities.insert(entity);
     }
 
-    pub fn fake(tree: BehaviourTree) -> AiActor {
+    /// Sets the spawn point and patrol route of the controlled entity
+    pub fn set_home(&mut self, home: Point2<f32>, waypoints: Vec<Point2<f32>>) {
+        self.tree_data.set_home(home);
+        self.tree_data.set_waypoints(waypoints);
+    }
+
+    pub fn set_think_period(&mut self, think_period: u64) {
+        self.think_period = if think_period == 0 { 1 } else { think_period };
+    }
+
+    // Chosen by the instance, the actor id would make the simulation depend on other instances
+    pub fn set_think_offset(&mut self, think_offset: u64) {
+        self.think_offset = think_offset;
+    }
+
+    /// Gives back the id the actor had in a recording
+    pub fn restore_id(&mut self, id: ActorId) {
+        self.id = id;
+    }
+
+    /// Swaps the behaviour tree, keeping the content of the blackboard
+    pub fn replace_tree(&mut self, tree: BehaviourTree) {
+        self.tree = tree;
+        self.last_status = None;
+        self.tree_data.clear_trace();
+    }
+
+    pub fn get_tree_name(&self) -> &str {
+        &self.tree_name
+    }
+
+    /// Dumps the state of the AI, including what happened during the last evaluation
+    pub fn debug_info(&self, source: Option<String>) -> AiDebug {
+        let tree = source.as_ref().and_then(|source| {
+            ai::debug_tree(source, &self.tree_name, self.tree_data.get_trace())
+        });
+        AiDebug {
+            behaviour_tree: self.tree_name.clone(),
+            source: source,
+            think_period: self.think_period,
+            asleep: self.asleep,
+            status: self.last_status,
+            leaves: self.tree_data.get_trace().to_vec(),
+            tree: tree,
+            blackboard: self.tree_data.to_blackboard(),
+        }
+    }
+
+    pub fn fake(tree_name: String, tree: BehaviourTree) -> AiActor {
         AiActor {
             id: Id::new(),
             entity: None,
             entities: Default::default(),
+            tree_name: tree_name,
             tree: tree,
             tree_data: BehaviourTreeData::new(),
+            last_status: None,
+            think_period: DEFAULT_THINK_PERIOD,
+            think_offset: 0,
+            asleep: false,
         }
     }
 }
+
+fn player_nearby(me: Id<Entity>, entities: &EntityStore) -> bool {
+    let position = match entities.get(me) {
+        Some(entity) => entity.get_position(),
+        None => return false,
+    };
+    !entities.in_range(position, WAKE_UP_SQDISTANCE.sqrt(), |e| e.is_player()).is_empty()
+}
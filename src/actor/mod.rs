@@ -1,9 +1,11 @@
 mod network;
 mod mob;
+mod interest;
+mod delta;
 
 use id::Id;
 
 pub use self::network::NetworkActor;
-pub use self::mob::AiActor;
+pub use self::mob::{AiActor,ThinkOutcome};
 
 pub type ActorId = Id<NetworkActor>;
@@ -2,20 +2,27 @@ use std::fmt::{self,Formatter,Display};
 use std::io::{self,Write,Error};
 use std::collections::{hash_set,HashSet,HashMap};
 
-use id::{self,Id,HasId};
+use id::{self,Id,HasId,HasForgeableId};
 use entity::{Entity,EntityStore};
 use messages::{self,Command,Notification,EntityOrder};
 use messages::{NetworkCommand};
 use network::{Client,ClientError};
 use actor::ActorId;
+use instance::recording::Recorder;
+
+use super::interest::Interest;
+use super::delta::StateUpdates;
 
 #[derive(Debug)]
 pub struct NetworkActor {
     id: ActorId,
     entities: HashSet<Id<Entity>>,
     // XXX Does this belong here?
-    client: Client,
+    // None for actors replayed from a recording
+    client: Option<Client>,
     commands: CommandBuffer,
+    interest: Interest,
+    updates: StateUpdates,
 }
 
 // A buffer of commands intented to apply some policies before adding a new command
@@ -46,14 +53,42 @@ impl NetworkActor {
         NetworkActor {
             id: id,
             entities: HashSet::new(),
-            client: client,
+            client: Some(client),
             commands: Default::default(),
+            interest: Interest::new(),
+            updates: StateUpdates::new(id.as_u64()),
         }
     }
 
+    /// An actor without client, whose orders are given directly (replays, tests)
+    pub fn replayed(id: ActorId) -> NetworkActor {
+        NetworkActor {
+            id: id,
+            entities: HashSet::new(),
+            client: None,
+            commands: Default::default(),
+            interest: Interest::new(),
+            updates: StateUpdates::new(id.as_u64()),
+        }
+    }
+
+    /// Gives an order to be executed during the next tick, as if the client had sent it
+    pub fn push_order(&mut self, order: EntityOrder) {
+        self.commands.orders.push(order);
+    }
+
+    /// Unregisters the actor during the next tick, as if the client had disconnected
+    pub fn disconnect(&mut self) {
+        self.commands.push(Command::UnregisterActor(self.id));
+    }
+
     fn receive_commands(&mut self) {
+        let client = match self.client {
+            Some(ref mut client) => client,
+            None => return,
+        };
         loop {
-            match self.client.recv() {
+            match client.recv() {
                 Ok(Some(NetworkCommand::EntityOrder(order))) => {
                     self.commands.orders.push(order);
                 }
@@ -78,9 +113,14 @@ impl NetworkActor {
     pub fn execute_orders(&mut self,
                       entities: &mut EntityStore,
                       notifications: &mut Vec<Notification>,
-                      _previous: &[Notification]) {
+                      _previous: &[Notification],
+                      recorder: &mut Option<Recorder>,
+                      tick: u64) {
         self.receive_commands();
         for order in self.commands.orders.drain(..) {
+            if let Some(ref mut recorder) = *recorder {
+                recorder.record_order(tick, self.id, &order);
+            }
             match id::get_id_if_exists(&self.entities, order.entity) {
                 None => {
                     warn!("Trying to give order to non-owned entity {}", order.entity);
@@ -92,8 +132,7 @@ impl NetworkActor {
                             let res = entity.apply(order.order);
                             match res {
                                 Err(_e) => {} // TODO: Send back error to network
-                                Ok(Some(notif)) => notifications.push(notif),
-                                Ok(None) => {}
+                                Ok(notifs) => notifications.extend(notifs),
                             }
                         }
                     }
@@ -108,11 +147,28 @@ impl NetworkActor {
         Ok(())
     }
 
+    /// Sends the notifications of a tick that concern the entities around the ones of the actor
+    ///
+    /// The state updates of the entities that have not changed are omitted
+    pub fn send_notifications(&mut self, entities: &EntityStore, notifications: &[Notification]) {
+        if self.client.is_none() {
+            return;
+        }
+        let filtered = self.interest.filter(&self.entities, entities, notifications);
+        for notification in self.updates.compress(filtered) {
+            self.send_message(notification);
+        }
+    }
+
     pub fn send_message(&mut self, message: Notification) {
+        let client = match self.client {
+            Some(ref mut client) => client,
+            None => return,
+        };
         match message.into() {
             Some(network_notif) => {
-                if let Err(e) = self.client.send(network_notif) {
-                    error!("Error when sending message to client {}: {:?}", self.client.uuid, e);
+                if let Err(e) = client.send(network_notif) {
+                    error!("Error when sending message to client {}: {:?}", client.uuid, e);
                     self.commands.push(Command::UnregisterActor(self.id));
                 }
             }
@@ -143,3 +199,6 @@ impl CommandBuffer {
 impl HasId for NetworkActor {
     type Type = u64;
 }
+
+// Replayed recordings must use the same ids as the original
+impl HasForgeableId for NetworkActor {}
@@ -0,0 +1,351 @@
+// Instrumentation of the behaviour trees, to know what an AI did during its last evaluation
+//
+// The composite nodes are provided by the behaviour_tree crate and cannot be inspected, so
+// only the leaves are traced. Each leaf knows its position among the leaves of its file, in
+// the order they were generated by the parser, which is the order of the source. To show the
+// status of every node, the source of the tree is parsed again, and the trace is matched with
+// its leaves by position. A composite node returns the result of the last child it has
+// visited.
+use std::iter::Peekable;
+use std::str::Chars;
+
+use behaviour_tree::tree::{BehaviourTreeNode,LeafNodeFactory,VisitResult};
+
+use data::{NodeStatus,LeafStatus,DebugNode};
+
+use super::{Context,ActionNode,ActionNodeFactory};
+
+pub fn to_status(result: &VisitResult) -> NodeStatus {
+    match *result {
+        VisitResult::Success => NodeStatus::Success,
+        VisitResult::Failure => NodeStatus::Failure,
+        VisitResult::Running => NodeStatus::Running,
+    }
+}
+
+/// Wraps a leaf factory so that every visit of the generated leaves is recorded
+#[derive(Clone)]
+pub struct TracedFactory {
+    index: usize,
+    name: String,
+    options: Option<String>,
+    inner: ActionNodeFactory,
+}
+
+impl TracedFactory {
+    pub fn new(index: usize, name: String, options: Option<String>, inner: ActionNodeFactory) -> TracedFactory {
+        TracedFactory {
+            index: index,
+            name: name,
+            options: options,
+            inner: inner,
+        }
+    }
+}
+
+impl LeafNodeFactory for TracedFactory {
+    type Output = ActionNode;
+    fn instanciate(&self) -> Self::Output {
+        Box::new(TracedNode {
+            index: self.index,
+            name: self.name.clone(),
+            options: self.options.clone(),
+            inner: self.inner.instanciate(),
+        })
+    }
+}
+
+pub struct TracedNode {
+    index: usize,
+    name: String,
+    options: Option<String>,
+    inner: ActionNode,
+}
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for TracedNode {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let result = self.inner.visit(context);
+        context.storage.trace.push(LeafStatus {
+            index: self.index,
+            name: self.name.clone(),
+            options: self.options.clone(),
+            status: to_status(&result),
+        });
+        result
+    }
+}
+
+/// The tree `name` defined in `source`, with the status of each node on the last evaluation
+///
+/// Returns None if the tree cannot be found in the source
+pub fn debug_tree(source: &str, name: &str, trace: &[LeafStatus]) -> Option<DebugNode> {
+    let mut parser = Parser {
+        chars: source.chars().peekable(),
+        leaves: 0,
+    };
+    let tree = match parser.tree(name) {
+        Ok(tree) => tree,
+        Err(()) => return None,
+    };
+    Some(annotate(&tree, trace))
+}
+
+fn annotate(node: &ParsedNode, trace: &[LeafStatus]) -> DebugNode {
+    let (status, children) = match node.children {
+        // Leaves that were not visited do not appear in the trace
+        None => {
+            let status = trace.iter()
+                .filter(|leaf| leaf.index == node.index && leaf.name == node.name)
+                .map(|leaf| leaf.status)
+                .last();
+            (status, Vec::new())
+        }
+        Some(ref children) => {
+            let annotated: Vec<_> = children.iter().map(|child| annotate(child, trace)).collect();
+            let status = annotated.iter().filter_map(|child| child.status).last();
+            (status, annotated)
+        }
+    };
+    DebugNode {
+        name: node.name.clone(),
+        options: node.options.clone(),
+        status: status,
+        children: children,
+    }
+}
+
+struct ParsedNode {
+    // Position among the leaves of the source, meaningless for a composite node
+    index: usize,
+    name: String,
+    options: Option<String>,
+    // None for a leaf
+    children: Option<Vec<ParsedNode>>,
+}
+
+// Reads the syntax of the behaviour_tree crate:
+//
+// tree NAME { NODE }
+// NODE: NAME, NAME(OPTIONS) or NAME { NODE, NODE, ... }
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    // Number of leaves parsed so far, in all the trees of the source
+    leaves: usize,
+}
+
+impl <'a> Parser<'a> {
+    fn tree(&mut self, name: &str) -> Result<ParsedNode,()> {
+        loop {
+            if try!(self.ident()) != "tree" {
+                return Err(());
+            }
+            let tree_name = try!(self.ident());
+            try!(self.expect('{'));
+            let root = try!(self.node());
+            try!(self.expect('}'));
+            if tree_name == name {
+                return Ok(root);
+            }
+        }
+    }
+
+    fn node(&mut self) -> Result<ParsedNode,()> {
+        let name = try!(self.ident());
+        let options = if self.eat('(') {
+            Some(try!(self.options()))
+        } else {
+            None
+        };
+        let mut index = 0;
+        let children = if self.eat('{') {
+            let mut children = Vec::new();
+            while !self.eat('}') {
+                children.push(try!(self.node()));
+                // The last comma is optional
+                self.eat(',');
+            }
+            Some(children)
+        } else {
+            index = self.leaves;
+            self.leaves += 1;
+            None
+        };
+        Ok(ParsedNode {
+            index: index,
+            name: name,
+            options: options,
+            children: children,
+        })
+    }
+
+    fn ident(&mut self) -> Result<String,()> {
+        self.skip_whitespace();
+        let mut ident = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_alphanumeric() && c != '_' {
+                break;
+            }
+            ident.push(c);
+            self.chars.next();
+        }
+        if ident.is_empty() {
+            Err(())
+        } else {
+            Ok(ident)
+        }
+    }
+
+    // Raw text up to the closing parenthesis, which is consumed
+    fn options(&mut self) -> Result<String,()> {
+        let mut options = String::new();
+        let mut in_string = false;
+        let mut depth = 0;
+        loop {
+            let c = match self.chars.next() {
+                Some(c) => c,
+                None => return Err(()),
+            };
+            match c {
+                '"' => in_string = !in_string,
+                '(' if !in_string => depth += 1,
+                ')' if !in_string => {
+                    if depth == 0 {
+                        return Ok(options);
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+            options.push(c);
+        }
+    }
+
+    // Consumes the character if it comes next
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&expected) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(),()> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use data::{NodeStatus,LeafStatus,DebugNode};
+    use super::debug_tree;
+
+    static SOURCE: &'static str = r#"
+tree other {
+        wander(5)
+}
+tree zombie {
+        priority {
+                sequence {
+                        get_highest_threat_target,
+                        walk_to_target,
+                },
+                print_text("BRAINS (again)"),
+        }
+}
+tree chaser {
+        priority {
+                sequence {
+                        was_attacked,
+                        walk_to_target,
+                },
+                walk_to_target,
+        }
+}
+"#;
+
+    // The index counts the leaves of all the trees of the source
+    fn leaf(index: usize, name: &str, status: NodeStatus) -> LeafStatus {
+        LeafStatus {
+            index: index,
+            name: String::from(name),
+            options: None,
+            status: status,
+        }
+    }
+
+    // Depth first
+    fn statuses(node: &DebugNode) -> Vec<Option<NodeStatus>> {
+        let mut all = vec![node.status];
+        for child in node.children.iter() {
+            all.extend(statuses(child));
+        }
+        all
+    }
+
+    #[test]
+    fn every_node_has_a_status() {
+        let trace = vec![
+            leaf(1, "get_highest_threat_target", NodeStatus::Success),
+            leaf(2, "walk_to_target", NodeStatus::Running),
+        ];
+        let tree = debug_tree(SOURCE, "zombie", &trace).unwrap();
+        assert_eq!(tree.name, "priority");
+        assert_eq!(tree.children[1].options, Some(String::from("\"BRAINS (again)\"")));
+        assert_eq!(statuses(&tree), vec![
+            Some(NodeStatus::Running),
+            Some(NodeStatus::Running),
+            Some(NodeStatus::Success),
+            Some(NodeStatus::Running),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn unvisited_branches() {
+        let trace = vec![
+            leaf(1, "get_highest_threat_target", NodeStatus::Failure),
+            leaf(3, "print_text", NodeStatus::Success),
+        ];
+        let tree = debug_tree(SOURCE, "zombie", &trace).unwrap();
+        assert_eq!(statuses(&tree), vec![
+            Some(NodeStatus::Success),
+            Some(NodeStatus::Failure),
+            Some(NodeStatus::Failure),
+            None,
+            Some(NodeStatus::Success),
+        ]);
+        assert!(debug_tree(SOURCE, "skeleton", &trace).is_none());
+        assert_eq!(debug_tree(SOURCE, "other", &[]).unwrap().options, Some(String::from("5")));
+    }
+
+    #[test]
+    fn repeated_leaves() {
+        let trace = vec![
+            leaf(4, "was_attacked", NodeStatus::Failure),
+            leaf(6, "walk_to_target", NodeStatus::Running),
+        ];
+        let tree = debug_tree(SOURCE, "chaser", &trace).unwrap();
+        assert_eq!(statuses(&tree), vec![
+            Some(NodeStatus::Running),
+            Some(NodeStatus::Failure),
+            Some(NodeStatus::Failure),
+            None,
+            Some(NodeStatus::Running),
+        ]);
+    }
+}
@@ -1,6 +1,7 @@
+use std::cell::Cell;
 use std::collections::HashMap;
 
-use nalgebra::{Vector2,FloatPoint};
+use nalgebra::{Point2,Vector2};
 
 use behaviour_tree::tree::{BehaviourTreeNode};
 use behaviour_tree::tree::{LeafNodeFactory,VisitResult};
@@ -8,7 +9,21 @@ use behaviour_tree::parser::Value;
 use behaviour_tree::FactoryProducer;
 
 use id::Id;
-use entity::{Entity,EntityStore,Direction};
+use entity::{Entity,EntityStore,Direction,CastOrder,CastTarget};
+use messages::Notification;
+use instance::InstanceRng;
+use instance::geometry::MapGeometry;
+use data::{LeafStatus,Blackboard,ThreatEntry,Waypoint};
+
+use self::movement::walk_towards;
+pub use self::threat::ThreatTable;
+pub use self::perception::{Perception,Event};
+pub use self::debug::{to_status,debug_tree};
+
+mod movement;
+mod threat;
+mod perception;
+mod debug;
 
 pub type ActionNode = Box<for<'a, 'b> BehaviourTreeNode<Context<'a, 'b>> + Send>;
 //pub type ActionNodeFactory = Box<LeafNodeFactory<Output=Box<for<'a> BehaviourTreeNode<Context<'a>>>>>;
@@ -37,19 +52,26 @@ where T: Clone,
 pub struct Context<'a, 'b> {
     pub me: Id<Entity>,
     pub entities: &'a mut EntityStore,
+    pub geometry: &'a MapGeometry,
     pub storage: &'b mut BehaviourTreeData,
+    // Random decisions must use this generator, for the simulation to be reproducible
+    pub rng: &'a mut InstanceRng,
 }
 
 impl <'a, 'b> Context<'a, 'b> {
     pub fn new(
         me: Id<Entity>,
         entities: &'a mut EntityStore,
+        geometry: &'a MapGeometry,
         storage: &'b mut BehaviourTreeData,
+        rng: &'a mut InstanceRng,
         ) -> Context<'a, 'b> {
         Context {
             me: me,
             entities: entities,
+            geometry: geometry,
             storage: storage,
+            rng: rng,
         }
     }
 }
@@ -59,6 +81,19 @@ pub struct BehaviourTreeData {
     map: HashMap<String,StoreKind>,
     target: Option<Id<Entity>>,
     path: Option<Path>,
+
+    // Spawn point of the entity, used by wander and return_home
+    home: Option<Point2<f32>>,
+    waypoints: Vec<Point2<f32>>,
+    next_waypoint: usize,
+    // Current destination of the wander behaviour
+    destination: Option<Point2<f32>>,
+    // Set when the entity has been pulled too far away, and is walking back home
+    returning_home: bool,
+    threat: ThreatTable,
+    perception: Perception,
+    // Leaves visited during the last evaluation
+    trace: Vec<LeafStatus>,
 }
 
 impl BehaviourTreeData {
@@ -67,12 +102,85 @@ impl BehaviourTreeData {
             map: HashMap::new(),
             target: None,
             path: None,
+            home: None,
+            waypoints: Vec::new(),
+            next_waypoint: 0,
+            destination: None,
+            returning_home: false,
+            threat: ThreatTable::new(),
+            perception: Perception::new(),
+            trace: Vec::new(),
         }
     }
 
     fn set_target(&mut self, target: Option<Id<Entity>>) {
         self.target = target;
     }
+
+    pub fn set_home(&mut self, home: Point2<f32>) {
+        self.home = Some(home);
+    }
+
+    pub fn set_waypoints(&mut self, waypoints: Vec<Point2<f32>>) {
+        self.waypoints = waypoints;
+        self.next_waypoint = 0;
+    }
+
+    /// Updates the perception and the threat table from the notifications of the previous tick
+    pub fn perceive(&mut self,
+                    me: Id<Entity>,
+                    entities: &EntityStore,
+                    previous: &[Notification]) {
+        let already_perceived = self.perception.events().len();
+        self.perception.perceive(me, entities, previous);
+        self.threat.decay();
+        for event in &self.perception.events()[already_perceived..] {
+            match *event {
+                Event::Attacked { source, amount } => self.threat.add(source, amount as f32),
+                Event::EntityLeft(id) | Event::EntityDied(id) => self.threat.remove(id),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn forget_perception(&mut self) {
+        self.perception.clear();
+    }
+
+    /// Must be called before each evaluation of the tree
+    pub fn clear_trace(&mut self) {
+        self.trace.clear();
+    }
+
+    pub fn get_trace(&self) -> &[LeafStatus] {
+        &self.trace
+    }
+
+    pub fn to_blackboard(&self) -> Blackboard {
+        fn waypoint(point: &Point2<f32>) -> Waypoint {
+            Waypoint { x: point.x, y: point.y }
+        }
+        Blackboard {
+            target: self.target,
+            home: self.home.as_ref().map(waypoint),
+            destination: self.destination.as_ref().map(waypoint),
+            waypoints: self.waypoints.iter().map(waypoint).collect(),
+            next_waypoint: self.next_waypoint,
+            returning_home: self.returning_home,
+            threat: self.threat.sorted().into_iter().map(|(id, threat)| {
+                ThreatEntry { entity: id.as_u64(), threat: threat }
+            }).collect(),
+            perception: self.perception.events().iter().map(|e| format!("{:?}", e)).collect(),
+        }
+    }
+
+    // Forget everything learned since the spawn
+    fn reset(&mut self) {
+        self.target = None;
+        self.path = None;
+        self.destination = None;
+        self.threat.clear();
+    }
 }
 
 #[derive(Clone,Debug)]
@@ -115,8 +223,8 @@ pub struct PrintText {
 }
 
 impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for PrintText {
-    fn visit(&mut self, _context: &mut Context) -> VisitResult {
-        println!("Message node: {}", self.text);
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        debug!("Message node of entity {}: {}", context.me, self.text);
         VisitResult::Success
     }
 }
@@ -147,21 +255,41 @@ impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for GetClosestTarget {
             Some((me, others)) => (me, others),
         };
         let my_position = me.get_position();
-        let mut closest_other = None;
-        let mut closest_other_sqdistance = self.max_sqdistance;
-        for other in others.iter() {
-            let sqdistance = my_position.distance_squared(&other.get_position());
-            if sqdistance < closest_other_sqdistance {
-                closest_other = Some(other.get_id());
-                closest_other_sqdistance = sqdistance;
-            }
-        }
+        let geometry = context.geometry;
+        let closest_other = others.nearest(my_position, self.max_sqdistance.sqrt(), |other| {
+            geometry.has_line_of_sight(my_position, other.get_position())
+        });
         context.storage.target = closest_other;
-        debug!("Get closest target: found {:?} at sqdist {}", closest_other, closest_other_sqdistance);
+        debug!("Get closest target: found {:?}", closest_other);
         VisitResult::Success
     }
 }
 
+// Succeeds if nothing blocks the sight between the entity and its target
+#[derive(Clone)]
+pub struct HasLineOfSight;
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for HasLineOfSight {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let target = match context.storage.target {
+            None => return VisitResult::Failure,
+            Some(target) => target,
+        };
+        let positions = context.entities.get(context.me).map(|e| e.get_position())
+            .and_then(|me| context.entities.get(target).map(|t| (me, t.get_position())));
+        match positions {
+            Some((me, target)) if context.geometry.has_line_of_sight(me, target) => {
+                VisitResult::Success
+            }
+            _ => VisitResult::Failure,
+        }
+    }
+}
+
+pub fn has_line_of_sight(_options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    Ok(Box::new(Prototype::new(HasLineOfSight)))
+}
+
 pub fn get_closest_target(options: &Option<Value>) -> Result<ActionNodeFactory, String> {
     // TODO
     Ok(Box::new(Prototype::new(GetClosestTarget { max_sqdistance: 10000.0 })))
@@ -187,47 +315,14 @@ impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for WalkToTarget {
             Some(id) => match others.get(id) {
                 None => {
                     warn!("Could not find target {}", id);
-                    me.walk(None);
+                    let _ = me.walk(None);
                     return VisitResult::Failure;
                 }
                 Some(o) => o,
             }
         };
         let vector = target.get_position() - me.get_position();
-        let abs_diff_x = vector.x.abs();
-        let abs_diff_y = vector.y.abs();
-        match me.get_orientation() {
-            Direction::East | Direction::West => {
-                if abs_diff_x > abs_diff_y/2.0 {
-                    if vector.x.is_sign_positive() {
-                        me.walk(Some(Direction::East));
-                    } else {
-                        me.walk(Some(Direction::West));
-                    }
-                } else {
-                    if vector.y.is_sign_positive() {
-                        me.walk(Some(Direction::North));
-                    } else {
-                        me.walk(Some(Direction::South));
-                    }
-                }
-            }
-            Direction::North | Direction::South => {
-                if abs_diff_x/2.0 > abs_diff_y {
-                    if vector.x.is_sign_positive() {
-                        me.walk(Some(Direction::East));
-                    } else {
-                        me.walk(Some(Direction::West));
-                    }
-                } else {
-                    if vector.y.is_sign_positive() {
-                        me.walk(Some(Direction::North));
-                    } else {
-                        me.walk(Some(Direction::South));
-                    }
-                }
-            }
-        }
+        walk_towards(me, vector);
         VisitResult::Running
     }
 }
@@ -237,10 +332,62 @@ pub fn walk_to_target(options: &Option<Value>) -> Result<ActionNodeFactory, Stri
     Ok(Box::new(Prototype::new(WalkToTarget)))
 }
 
+// Orders a cast of the skill on the current target
+// Running while a previous cast is in progress, fails if the entity cannot cast
+#[derive(Clone)]
+pub struct CastSkill {
+    skill: String,
+}
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for CastSkill {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let target = match context.storage.target {
+            None => return VisitResult::Failure,
+            Some(target) => target,
+        };
+        let me = match context.entities.get_mut(context.me) {
+            None => {
+                warn!("Main entity {} was not found in entities list", context.me);
+                return VisitResult::Failure;
+            }
+            Some(me) => me,
+        };
+        if me.is_casting() {
+            return VisitResult::Running;
+        }
+        // Moving would interrupt the cast
+        let _ = me.walk(None);
+        let order = CastOrder::new(self.skill.clone(), CastTarget::Entity(target.into()));
+        match me.cast(order) {
+            Ok(()) => VisitResult::Success,
+            Err(_) => VisitResult::Failure,
+        }
+    }
+}
+
+pub fn cast_skill(options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    let skill = match options {
+        &Some(Value::String(ref skill)) => skill.clone(),
+        other => return Err(format!("Expected skill name, found {:?}", other)),
+    };
+    Ok(Box::new(Prototype::new(CastSkill { skill: skill })))
+}
+
+// Extracts a numeric parameter from the options of a leaf
+fn get_number(options: &Option<Value>) -> Result<f32, String> {
+    match options {
+        &Some(Value::Integer(i)) => Ok(i as f32),
+        &Some(Value::Float(f)) => Ok(f as f32),
+        other => Err(format!("Expected number, found {:?}", other)),
+    }
+}
+
 
 #[derive(Default)]
 pub struct LeavesCollection {
     inner: HashMap<String,ActionNodeFactoryFactory>,
+    // Index of the next leaf generated, the leaves of a file are generated in order
+    next_index: Cell<usize>,
 }
 
 macro_rules! insert_all {
@@ -265,9 +412,15 @@ impl LeavesCollection {
     pub fn new() -> LeavesCollection {
         LeavesCollection {
             inner: HashMap::new(),
+            next_index: Cell::new(0),
         }
     }
 
+    /// Numbers the leaves from 0 again, before parsing a new file
+    pub fn reset_index(&self) {
+        self.next_index.set(0);
+    }
+
     pub fn register_function(
         &mut self,
         key: String,
@@ -281,6 +434,16 @@ impl LeavesCollection {
             "print_text" => print_text,
             "get_closest_target" => get_closest_target,
             "walk_to_target" => walk_to_target,
+            "has_line_of_sight" => has_line_of_sight,
+            "cast_skill" => cast_skill,
+            "wander" => movement::wander,
+            "patrol" => movement::patrol,
+            "flee" => movement::flee,
+            "return_home" => movement::return_home,
+            "get_highest_threat_target" => threat::get_highest_threat_target,
+            "was_attacked" => perception::was_attacked,
+            "heard_say" => perception::heard_say,
+            "target_lost" => perception::target_lost,
             //"increment" => increment,
 
             );
@@ -296,7 +459,10 @@ impl FactoryProducer for LeavesCollection {
             None => Err(format!("Could not find leaf with name {}", name)),
             Some(fact_fact) => {
                 let fact = try!(fact_fact(option));
-                Ok(fact) 
+                let options = option.as_ref().map(|o| format!("{:?}", o));
+                let index = self.next_index.get();
+                self.next_index.set(index + 1);
+                Ok(Box::new(debug::TracedFactory::new(index, String::from(name), options, fact)))
             }
         }
     }
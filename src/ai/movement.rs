@@ -0,0 +1,373 @@
+// Movement behaviours that do not depend on a target: wandering, patrolling, fleeing and
+// leashing back to the spawn point
+use std::f32::consts::PI;
+
+use nalgebra::{Point2,Vector2,FloatPoint};
+use rand::Rng;
+
+use behaviour_tree::tree::{BehaviourTreeNode,VisitResult};
+use behaviour_tree::parser::Value;
+
+use entity::{Entity,Direction};
+
+use super::{Context,ActionNodeFactory,Prototype,get_number};
+
+// Distance under which a destination is considered reached
+// An AI walks 0.25 units per tick at the default speed and tick rate
+static ARRIVAL_SQDISTANCE: f32 = 1.0;
+
+/// Makes the entity walk in the direction of `vector`
+///
+/// An entity can only walk in the four cardinal directions, so the current orientation
+/// is favoured to avoid changing direction every tick when walking in diagonal
+pub fn walk_towards(me: &mut Entity, vector: Vector2<f32>) {
+    let abs_diff_x = vector.x.abs();
+    let abs_diff_y = vector.y.abs();
+    let horizontal = match me.get_orientation() {
+        Direction::East | Direction::West => abs_diff_x > abs_diff_y/2.0,
+        Direction::North | Direction::South => abs_diff_x/2.0 > abs_diff_y,
+    };
+    let direction = if horizontal {
+        if vector.x.is_sign_positive() {
+            Direction::East
+        } else {
+            Direction::West
+        }
+    } else {
+        if vector.y.is_sign_positive() {
+            Direction::North
+        } else {
+            Direction::South
+        }
+    };
+    // Refused while attacking or casting, the entity walks once it is done
+    let _ = me.walk(Some(direction));
+}
+
+// Walks to destination, returns true (and stops the entity) if it has been reached
+fn walk_to_point(me: &mut Entity, destination: Point2<f32>) -> bool {
+    let position = me.get_position();
+    if position.distance_squared(&destination) < ARRIVAL_SQDISTANCE {
+        let _ = me.walk(None);
+        true
+    } else {
+        walk_towards(me, destination - position);
+        false
+    }
+}
+
+#[derive(Clone)]
+pub struct Wander {
+    radius: f32,
+}
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for Wander {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let me = match context.entities.get_mut(context.me) {
+            None => {
+                warn!("Main entity {} was not found in entities list", context.me);
+                return VisitResult::Failure;
+            }
+            Some(me) => me,
+        };
+        let home = match context.storage.home {
+            None => return VisitResult::Failure,
+            Some(home) => home,
+        };
+        let destination = match context.storage.destination {
+            Some(destination) => destination,
+            None => {
+                // Uniform distribution in the disc around the spawn point
+                let angle = context.rng.gen_range(0.0, 2.0 * PI);
+                let distance = self.radius * context.rng.gen::<f32>().sqrt();
+                let destination = home + Vector2::new(angle.cos(), angle.sin()) * distance;
+                context.storage.destination = Some(destination);
+                destination
+            }
+        };
+        if walk_to_point(me, destination) {
+            context.storage.destination = None;
+            VisitResult::Success
+        } else {
+            VisitResult::Running
+        }
+    }
+}
+
+pub fn wander(options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    let radius = try!(get_number(options));
+    Ok(Box::new(Prototype::new(Wander { radius: radius })))
+}
+
+#[derive(Clone)]
+pub struct Patrol;
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for Patrol {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let me = match context.entities.get_mut(context.me) {
+            None => {
+                warn!("Main entity {} was not found in entities list", context.me);
+                return VisitResult::Failure;
+            }
+            Some(me) => me,
+        };
+        let storage = &mut context.storage;
+        let waypoint = match storage.waypoints.get(storage.next_waypoint) {
+            None => return VisitResult::Failure,
+            Some(waypoint) => *waypoint,
+        };
+        if walk_to_point(me, waypoint) {
+            storage.next_waypoint = (storage.next_waypoint + 1) % storage.waypoints.len();
+        }
+        VisitResult::Running
+    }
+}
+
+pub fn patrol(_options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    Ok(Box::new(Prototype::new(Patrol)))
+}
+
+#[derive(Clone)]
+pub struct Flee {
+    pv_threshold: u64,
+}
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for Flee {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let (me, mut others) = match context.entities.get_mut_wrapper(context.me) {
+            None => {
+                warn!("Main entity {} was not found in entities list", context.me);
+                return VisitResult::Failure;
+            }
+            Some((me, others)) => (me, others),
+        };
+        if me.get_pv() >= self.pv_threshold {
+            return VisitResult::Failure;
+        }
+        let target = match context.storage.target {
+            None => return VisitResult::Failure,
+            Some(id) => match others.get(id) {
+                None => return VisitResult::Failure,
+                Some(o) => o,
+            }
+        };
+        let vector = me.get_position() - target.get_position();
+        walk_towards(me, vector);
+        VisitResult::Running
+    }
+}
+
+pub fn flee(options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    let threshold = try!(get_number(options));
+    Ok(Box::new(Prototype::new(Flee { pv_threshold: threshold as u64 })))
+}
+
+// Leash: if the entity is pulled further than max_distance from its spawn point, it
+// forgets its target and walks back home, where it is restored to full health
+//
+// Fails when no leashing is in progress, so it can be put first in a priority node
+#[derive(Clone)]
+pub struct ReturnHome {
+    max_sqdistance: f32,
+}
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for ReturnHome {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let me = match context.entities.get_mut(context.me) {
+            None => {
+                warn!("Main entity {} was not found in entities list", context.me);
+                return VisitResult::Failure;
+            }
+            Some(me) => me,
+        };
+        let home = match context.storage.home {
+            None => return VisitResult::Failure,
+            Some(home) => home,
+        };
+        if !context.storage.returning_home {
+            if me.get_position().distance_squared(&home) <= self.max_sqdistance {
+                return VisitResult::Failure;
+            }
+            debug!("Entity {} pulled too far from home, returning", context.me);
+            context.storage.returning_home = true;
+            context.storage.reset();
+        }
+        if walk_to_point(me, home) {
+            me.restore();
+            context.storage.returning_home = false;
+            VisitResult::Success
+        } else {
+            VisitResult::Running
+        }
+    }
+}
+
+pub fn return_home(options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    let max_distance = try!(get_number(options));
+    Ok(Box::new(Prototype::new(ReturnHome { max_sqdistance: max_distance * max_distance })))
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::{Point2,FloatPoint};
+    use uuid::Uuid;
+
+    use behaviour_tree::tree::{BehaviourTreeNode,VisitResult};
+
+    use id::Id;
+    use entity::{self,Entity,EntityStore,Projectiles};
+    use data::Player;
+    use instance::{self,InstanceRng};
+    use instance::geometry::MapGeometry;
+    use scripts::AaribaScripts;
+    use ai::{BehaviourTreeData,Context};
+    use tests::fixtures::{monster,store};
+    use super::{Wander,Patrol,Flee,ReturnHome};
+
+    static TICK_DURATION: f32 = 0.05;
+
+    // A single AI entity, whose leaf is visited before the calculation of each tick
+    struct World {
+        me: Id<Entity>,
+        entities: EntityStore,
+        geometry: MapGeometry,
+        scripts: AaribaScripts,
+        rng: InstanceRng,
+        storage: BehaviourTreeData,
+    }
+
+    impl World {
+        fn new(me: Entity, home: Point2<f32>) -> World {
+            let combat = String::from(include_str!("../../scripts/combat.aariba"));
+            let mut scripts = AaribaScripts::new();
+            scripts.append("combat", "combat.aariba", combat).unwrap();
+            let mut storage = BehaviourTreeData::new();
+            storage.set_home(home);
+            let id = me.get_id();
+            World {
+                me: id,
+                entities: store(vec![me]),
+                geometry: MapGeometry::empty(),
+                scripts: scripts,
+                rng: instance::seeded_rng(42),
+                storage: storage,
+            }
+        }
+
+        fn tick<N>(&mut self, leaf: &mut N) -> VisitResult
+        where N: for<'a,'b> BehaviourTreeNode<Context<'a,'b>> {
+            let result = {
+                let mut context = Context::new(self.me,
+                                               &mut self.entities,
+                                               &self.geometry,
+                                               &mut self.storage,
+                                               &mut self.rng);
+                leaf.visit(&mut context)
+            };
+            entity::update(&mut self.entities,
+                           &mut Projectiles::new(),
+                           &self.geometry,
+                           &mut Vec::new(),
+                           &self.scripts,
+                           &mut self.rng,
+                           TICK_DURATION);
+            result
+        }
+
+        fn me(&self) -> &Entity {
+            self.entities.get(self.me).unwrap()
+        }
+    }
+
+    fn is_success(result: VisitResult) -> bool {
+        match result {
+            VisitResult::Success => true,
+            _ => false,
+        }
+    }
+
+    fn is_failure(result: VisitResult) -> bool {
+        match result {
+            VisitResult::Failure => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn wander_stays_around_home() {
+        let home = Point2::new(0.0, 0.0);
+        let mut world = World::new(monster(0.0, 0.0), home);
+        let mut wander = Wander { radius: 5.0 };
+        let mut furthest: f32 = 0.0;
+        for _ in 0..400 {
+            world.tick(&mut wander);
+            let distance = world.me().get_position().distance(&home);
+            // The destination is reached when closer than one unit
+            assert!(distance <= 5.0 + 1.0);
+            furthest = furthest.max(distance);
+        }
+        assert!(furthest > 0.0);
+    }
+
+    #[test]
+    fn patrol_goes_through_the_waypoints() {
+        let mut world = World::new(monster(0.0, 0.0), Point2::new(0.0, 0.0));
+        world.storage.set_waypoints(vec![Point2::new(5.0, 0.0), Point2::new(5.0, 5.0)]);
+        let mut patrol = Patrol;
+        let mut reached = Vec::new();
+        for _ in 0..200 {
+            let next = world.storage.next_waypoint;
+            world.tick(&mut patrol);
+            if world.storage.next_waypoint != next {
+                reached.push(next);
+            }
+        }
+        assert!(reached.len() >= 3);
+        assert_eq!(&reached[..3], &[0, 1, 0]);
+    }
+
+    #[test]
+    fn flee_when_low_pv() {
+        let mut world = World::new(monster(0.0, 0.0), Point2::new(0.0, 0.0));
+        let threat = monster(2.0, 0.0);
+        world.storage.target = Some(threat.get_id());
+        world.entities.push(threat);
+
+        // Monsters have 100 pv
+        assert!(is_failure(world.tick(&mut Flee { pv_threshold: 100 })));
+        assert_eq!(world.me().get_position(), Point2::new(0.0, 0.0));
+
+        let mut flee = Flee { pv_threshold: 101 };
+        for _ in 0..20 {
+            world.tick(&mut flee);
+        }
+        assert!(world.me().get_position().x < -1.0);
+    }
+
+    #[test]
+    fn return_home_restores_pv() {
+        let player: Option<Player> = Entity::fake_player(Id::forge(Uuid::new_v4())).into();
+        let mut player = player.unwrap();
+        player.current_pv = 40;
+        player.position.x = 20.0;
+        let home = Point2::new(0.0, 0.0);
+        let mut world = World::new(Entity::from(player), home);
+        world.storage.target = Some(Id::forge(42));
+        let mut return_home = ReturnHome { max_sqdistance: 10.0 * 10.0 };
+
+        let mut arrived = false;
+        for _ in 0..100 {
+            if is_success(world.tick(&mut return_home)) {
+                arrived = true;
+                break;
+            }
+            assert!(world.storage.target.is_none());
+            assert_eq!(world.me().get_pv(), 40);
+        }
+        assert!(arrived);
+        assert!(world.me().get_position().distance(&home) < 1.0);
+        assert_eq!(world.me().get_pv(), 100);
+        // Close enough to home, nothing to do
+        assert!(is_failure(world.tick(&mut return_home)));
+    }
+}
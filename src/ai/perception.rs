@@ -0,0 +1,245 @@
+// Perception of the world by an AI
+//
+// The notifications generated during the previous tick are translated into events
+// relevant to the AI, that the behaviour tree can react to through condition leaves
+use nalgebra::FloatPoint;
+
+use behaviour_tree::tree::{BehaviourTreeNode,VisitResult};
+use behaviour_tree::parser::Value;
+
+use id::{Id,WeakId};
+use entity::{Entity,EntityStore};
+use messages::Notification;
+
+use super::{Context,ActionNodeFactory,Prototype};
+
+// An entity further than that cannot be heard
+static HEARING_SQDISTANCE: f32 = 400.0;
+
+#[derive(Debug,Clone)]
+pub enum Event {
+    Attacked {
+        source: WeakId<Entity>,
+        amount: u64,
+    },
+    Heard {
+        speaker: WeakId<Entity>,
+        message: String,
+    },
+    EntityAppeared(WeakId<Entity>),
+    EntityLeft(WeakId<Entity>),
+    EntityDied(WeakId<Entity>),
+}
+
+/// Events perceived since the last evaluation of the behaviour tree
+#[derive(Debug,Clone,Default)]
+pub struct Perception {
+    events: Vec<Event>,
+}
+
+impl Perception {
+    pub fn new() -> Perception {
+        Perception {
+            events: Vec::new(),
+        }
+    }
+
+    /// Adds the events found in `previous` to the current ones
+    pub fn perceive(&mut self,
+                    me: Id<Entity>,
+                    entities: &EntityStore,
+                    previous: &[Notification]) {
+        let my_position = match entities.get(me) {
+            Some(entity) => entity.get_position(),
+            None => return,
+        };
+        for notif in previous {
+            let event = match *notif {
+                Notification::Damage { source, victim, amount, .. } => {
+                    if victim != me.as_u64() || source == victim {
+                        continue;
+                    }
+                    Event::Attacked { source: WeakId::new(source), amount: amount }
+                }
+                Notification::Say { entity, ref message } => {
+                    if entity == me.as_u64() {
+                        continue;
+                    }
+                    let in_range = entities.get(WeakId::new(entity))
+                        .map(|e| e.get_position().distance_squared(&my_position) <= HEARING_SQDISTANCE)
+                        .unwrap_or(false);
+                    if !in_range {
+                        continue;
+                    }
+                    Event::Heard { speaker: WeakId::new(entity), message: message.clone() }
+                }
+                Notification::NewEntity { entity, .. } => Event::EntityAppeared(WeakId::new(entity)),
+                Notification::EntityHasQuit { entity } => Event::EntityLeft(WeakId::new(entity)),
+                Notification::Death { entity } => Event::EntityDied(WeakId::new(entity)),
+                _ => continue,
+            };
+            self.events.push(event);
+        }
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Forgets the events, once the behaviour tree had a chance to react to them
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn was_attacked(&self) -> bool {
+        self.events.iter().any(|e| if let Event::Attacked { .. } = *e { true } else { false })
+    }
+
+    /// True if a message containing the keyword, in lower case, was heard
+    pub fn heard(&self, keyword: &str) -> bool {
+        self.events.iter().any(|e| match *e {
+            Event::Heard { ref message, .. } => message.to_lowercase().contains(keyword),
+            _ => false,
+        })
+    }
+
+    pub fn has_left(&self, entity: WeakId<Entity>) -> bool {
+        self.events.iter().any(|e| match *e {
+            Event::EntityLeft(id) | Event::EntityDied(id) => id == entity,
+            _ => false,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct WasAttacked;
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for WasAttacked {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        if context.storage.perception.was_attacked() {
+            VisitResult::Success
+        } else {
+            VisitResult::Failure
+        }
+    }
+}
+
+pub fn was_attacked(_options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    Ok(Box::new(Prototype::new(WasAttacked)))
+}
+
+#[derive(Clone)]
+pub struct HeardSay {
+    keyword: String,
+}
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for HeardSay {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        if context.storage.perception.heard(&self.keyword) {
+            VisitResult::Success
+        } else {
+            VisitResult::Failure
+        }
+    }
+}
+
+pub fn heard_say(options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    let keyword = match options {
+        &Some(Value::String(ref keyword)) => keyword.replace("_", " ").to_lowercase(),
+        other => return Err(format!("Expected keyword, found {:?}", other)),
+    };
+    Ok(Box::new(Prototype::new(HeardSay { keyword: keyword })))
+}
+
+// Succeeds if the current target left the map or died, and forgets it
+#[derive(Clone)]
+pub struct TargetLost;
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for TargetLost {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        let target = match context.storage.target {
+            None => return VisitResult::Failure,
+            Some(target) => target,
+        };
+        if context.storage.perception.has_left(target.into()) ||
+            context.entities.get(target).is_none() {
+                context.storage.target = None;
+                VisitResult::Success
+        } else {
+            VisitResult::Failure
+        }
+    }
+}
+
+pub fn target_lost(_options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    Ok(Box::new(Prototype::new(TargetLost)))
+}
+
+#[cfg(test)]
+mod test {
+    use id::WeakId;
+    use entity::{HitOutcome,DamageType};
+    use messages::Notification;
+    use tests::fixtures::{monster,store};
+    use super::{Perception,Event};
+
+    fn damage(source: u64, victim: u64) -> Notification {
+        Notification::Damage {
+            source: source,
+            victim: victim,
+            amount: 10,
+            outcome: HitOutcome::Hit,
+            damage_type: DamageType::Physical,
+        }
+    }
+
+    #[test]
+    fn hearing_range() {
+        // The hearing range is 20
+        let me = monster(0.0, 0.0);
+        let close = monster(15.0, 0.0);
+        let far = monster(25.0, 0.0);
+        let (id, close_id, far_id) = (me.get_id(), close.get_id().as_u64(), far.get_id().as_u64());
+        let entities = store(vec![me, close, far]);
+        let mut perception = Perception::new();
+        perception.perceive(id,
+                            &entities,
+                            &[Notification::say(close_id, String::from("Hello")),
+                              Notification::say(far_id, String::from("Goodbye")),
+                              Notification::say(id.as_u64(), String::from("Talking to myself"))]);
+        let speakers: Vec<_> = perception.events().iter().filter_map(|e| match *e {
+            Event::Heard { speaker, .. } => Some(speaker),
+            _ => None,
+        }).collect();
+        assert_eq!(speakers, vec![WeakId::new(close_id)]);
+    }
+
+    #[test]
+    fn keywords() {
+        let me = monster(0.0, 0.0);
+        let other = monster(1.0, 0.0);
+        let (id, other_id) = (me.get_id(), other.get_id().as_u64());
+        let entities = store(vec![me, other]);
+        let mut perception = Perception::new();
+        perception.perceive(id, &entities, &[Notification::say(other_id, String::from("Give me the GOLD now"))]);
+        assert!(perception.heard("gold"));
+        assert!(perception.heard("the gold"));
+        assert!(!perception.heard("silver"));
+
+        perception.clear();
+        assert!(!perception.heard("gold"));
+    }
+
+    #[test]
+    fn attacked_by_others_only() {
+        let me = monster(0.0, 0.0);
+        let other = monster(1.0, 0.0);
+        let (id, other_id) = (me.get_id(), other.get_id().as_u64());
+        let entities = store(vec![me, other]);
+        let mut perception = Perception::new();
+        perception.perceive(id, &entities, &[damage(id.as_u64(), id.as_u64()), damage(id.as_u64(), other_id)]);
+        assert!(!perception.was_attacked());
+        perception.perceive(id, &entities, &[damage(other_id, id.as_u64())]);
+        assert!(perception.was_attacked());
+    }
+}
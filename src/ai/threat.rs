@@ -0,0 +1,133 @@
+// Threat (aggro) table used by AIs to choose their target
+use std::collections::HashMap;
+use std::cmp::Ordering;
+
+use behaviour_tree::tree::{BehaviourTreeNode,VisitResult};
+use behaviour_tree::parser::Value;
+
+use id::WeakId;
+use entity::Entity;
+
+use super::{Context,ActionNodeFactory,Prototype};
+
+// Proportion of threat kept from one tick to the next
+// With a 50ms tick, threat is halved in about 7 seconds
+static THREAT_DECAY: f32 = 0.995;
+// Entries under this value are forgotten
+static MIN_THREAT: f32 = 0.1;
+
+#[derive(Debug,Clone,Default)]
+pub struct ThreatTable {
+    threats: HashMap<WeakId<Entity>, f32>,
+}
+
+impl ThreatTable {
+    pub fn new() -> ThreatTable {
+        ThreatTable {
+            threats: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entity: WeakId<Entity>, threat: f32) {
+        *self.threats.entry(entity).or_insert(0.0) += threat;
+    }
+
+    pub fn remove(&mut self, entity: WeakId<Entity>) {
+        self.threats.remove(&entity);
+    }
+
+    pub fn clear(&mut self) {
+        self.threats.clear();
+    }
+
+    pub fn get(&self, entity: WeakId<Entity>) -> f32 {
+        self.threats.get(&entity).cloned().unwrap_or(0.0)
+    }
+
+    /// Reduces every threat, and forgets the entities that are no longer threatening
+    pub fn decay(&mut self) {
+        for threat in self.threats.values_mut() {
+            *threat *= THREAT_DECAY;
+        }
+        self.threats.retain(|_, threat| *threat >= MIN_THREAT);
+    }
+
+    /// Entities sorted by decreasing threat, then by increasing id so that ties do not depend
+    /// on the order of the HashMap
+    ///
+    /// A NaN threat counts as no threat at all
+    pub fn sorted(&self) -> Vec<(WeakId<Entity>, f32)> {
+        let mut sorted: Vec<_> = self.threats.iter()
+            .map(|(id, threat)| (*id, if threat.is_nan() { 0.0 } else { *threat }))
+            .collect();
+        sorted.sort_by(|a, b| {
+            match b.1.partial_cmp(&a.1) {
+                Some(Ordering::Equal) | None => a.0.as_u64().cmp(&b.0.as_u64()),
+                Some(ordering) => ordering,
+            }
+        });
+        sorted
+    }
+}
+
+#[derive(Clone)]
+pub struct GetHighestThreatTarget;
+
+impl <'a,'b> BehaviourTreeNode<Context<'a,'b>> for GetHighestThreatTarget {
+    fn visit(&mut self, context: &mut Context) -> VisitResult {
+        // The table can reference entities that left the map, skip them
+        let target = context.storage.threat.sorted().into_iter()
+            .filter_map(|(id, _)| context.entities.get(id).map(|e| e.get_id()))
+            .filter(|id| *id != context.me)
+            .next();
+        context.storage.target = target;
+        debug!("Get highest threat target: found {:?}", target);
+        match target {
+            Some(_) => VisitResult::Success,
+            None => VisitResult::Failure,
+        }
+    }
+}
+
+pub fn get_highest_threat_target(_options: &Option<Value>) -> Result<ActionNodeFactory, String> {
+    Ok(Box::new(Prototype::new(GetHighestThreatTarget)))
+}
+
+#[cfg(test)]
+mod test {
+    use id::WeakId;
+    use super::ThreatTable;
+
+    #[test]
+    fn highest_threat_first() {
+        let mut table = ThreatTable::new();
+        table.add(WeakId::new(1), 10.0);
+        table.add(WeakId::new(2), 25.0);
+        table.add(WeakId::new(1), 20.0);
+        let sorted = table.sorted();
+        assert_eq!(sorted[0].0, WeakId::new(1));
+        assert_eq!(sorted[1].0, WeakId::new(2));
+    }
+
+    #[test]
+    fn ties_and_nan() {
+        let mut table = ThreatTable::new();
+        for id in vec![5, 3, 8, 1] {
+            table.add(WeakId::new(id), 10.0);
+        }
+        table.add(WeakId::new(2), ::std::f32::NAN);
+        let ids: Vec<_> = table.sorted().into_iter().map(|(id, _)| id.as_u64()).collect();
+        assert_eq!(ids, vec![1, 3, 5, 8, 2]);
+        assert_eq!(table.sorted()[4].1, 0.0);
+    }
+
+    #[test]
+    fn decay_forgets() {
+        let mut table = ThreatTable::new();
+        table.add(WeakId::new(1), 1.0);
+        for _ in 0..1000 {
+            table.decay();
+        }
+        assert!(table.sorted().is_empty());
+    }
+}
@@ -0,0 +1,371 @@
+extern crate lycan_serialize;
+extern crate env_logger;
+extern crate docopt;
+extern crate rustc_serialize;
+extern crate byteorder;
+extern crate rand;
+extern crate uuid;
+extern crate hyper;
+extern crate time;
+
+use std::io::{self,Read,Write,BufReader};
+use std::net::TcpStream;
+use std::collections::BTreeMap;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicUsize,AtomicBool,Ordering};
+use std::thread;
+use std::time::Duration;
+
+use docopt::Docopt;
+use byteorder::{ReadBytesExt,LittleEndian};
+use rand::Rng;
+use uuid::Uuid;
+use hyper::header::{Headers,ContentType};
+use hyper::status::StatusCode;
+
+use lycan_serialize::{
+    AuthenticationToken,
+    ErrorCode,
+    Direction,
+    Order,
+    EntityOrder,
+    Command,
+    GameCommand,
+    Notification,
+};
+
+// A say order whose notification has not come back after that long is forgotten, in ns
+static SAY_TIMEOUT: u64 = 10_000_000_000;
+
+static USAGE: &'static str = r#"
+Connects bots to a Lycan server, and drives their entities with random orders.
+
+The bots first use the well-known fake authentication tokens, then register new
+players through the management API.
+
+Usage:
+    lycan-bot [options]
+
+Options:
+    -a ADDRESS, --address ADDRESS       Address of the game server [default: 127.0.0.1:7777]
+    -m URL, --management URL            URL of the management API [default: http://127.0.0.1:9001/api/v1]
+    -s SECRET, --secret SECRET          Access token of the management API [default: abcdefgh]
+    -n COUNT, --bots COUNT              Number of bots [default: 10]
+    -o PERIOD, --order PERIOD           Time between two orders of a bot, in ms [default: 200]
+    -r PERIOD, --report PERIOD          Time between two reports, in seconds [default: 5]
+    -d DURATION, --duration DURATION    Time before the bots disconnect, in seconds (0 to run forever) [default: 0]
+    -h, --help                          Prints this message
+"#;
+
+#[derive(RustcDecodable,Debug)]
+struct Args {
+    flag_address: String,
+    flag_management: String,
+    flag_secret: String,
+    flag_bots: usize,
+    flag_order: u64,
+    flag_report: u64,
+    flag_duration: u64,
+}
+
+// Shared by all the bots
+#[derive(Default)]
+struct Statistics {
+    connected: AtomicUsize,
+    connection_failures: AtomicUsize,
+    authentication_failures: AtomicUsize,
+    disconnections: AtomicUsize,
+    orders: AtomicUsize,
+    notifications: AtomicUsize,
+    // Round trip of the say orders, in microseconds
+    latency_total: AtomicUsize,
+    latency_count: AtomicUsize,
+    latency_max: AtomicUsize,
+}
+
+impl Statistics {
+    fn add_latency(&self, latency: usize) {
+        self.latency_total.fetch_add(latency, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        let mut max = self.latency_max.load(Ordering::Relaxed);
+        while latency > max {
+            let previous = self.latency_max.compare_and_swap(max, latency, Ordering::Relaxed);
+            if previous == max {
+                break;
+            }
+            max = previous;
+        }
+    }
+}
+
+// Values of the counters at the previous report
+#[derive(Default)]
+struct Report {
+    orders: usize,
+    notifications: usize,
+    latency_total: usize,
+    latency_count: usize,
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| e.exit());
+    env_logger::init().unwrap();
+
+    let identities = get_identities(args.flag_bots, &args.flag_management, &args.flag_secret);
+    if identities.len() < args.flag_bots {
+        let _ = writeln!(io::stderr(), "Only {} bots out of {} could get an identity",
+                         identities.len(), args.flag_bots);
+    }
+
+    let statistics = Arc::new(Statistics::default());
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut bots = Vec::new();
+    for (index, (uuid, token)) in identities.into_iter().enumerate() {
+        let address = args.flag_address.clone();
+        let statistics = statistics.clone();
+        let stop = stop.clone();
+        let period = args.flag_order;
+        bots.push(thread::spawn(move || {
+            run_bot(index, &address, uuid, token, period, &statistics, &stop);
+        }));
+        // Do not connect everybody at once
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let start = time::precise_time_ns();
+    let mut previous = Report::default();
+    loop {
+        thread::sleep(Duration::from_secs(args.flag_report));
+        print_report(&statistics, &mut previous, args.flag_report);
+        let elapsed = (time::precise_time_ns() - start) / 1_000_000_000;
+        if args.flag_duration != 0 && elapsed >= args.flag_duration {
+            break;
+        }
+        if statistics.connected.load(Ordering::Relaxed) == 0 {
+            let _ = writeln!(io::stderr(), "No bot is connected any more");
+            break;
+        }
+    }
+    stop.store(true, Ordering::Relaxed);
+    for bot in bots {
+        let _ = bot.join();
+    }
+}
+
+fn print_report(statistics: &Statistics, previous: &mut Report, period: u64) {
+    let orders = statistics.orders.load(Ordering::Relaxed);
+    let notifications = statistics.notifications.load(Ordering::Relaxed);
+    let latency_total = statistics.latency_total.load(Ordering::Relaxed);
+    let latency_count = statistics.latency_count.load(Ordering::Relaxed);
+    let latency = if latency_count > previous.latency_count {
+        (latency_total - previous.latency_total) / (latency_count - previous.latency_count)
+    } else {
+        0
+    };
+    println!("connected: {} | failures: connection {} authentication {} disconnection {} | \
+              orders/s: {} | notifications/s: {} | latency: average {} ms max {} ms",
+             statistics.connected.load(Ordering::Relaxed),
+             statistics.connection_failures.load(Ordering::Relaxed),
+             statistics.authentication_failures.load(Ordering::Relaxed),
+             statistics.disconnections.load(Ordering::Relaxed),
+             (orders - previous.orders) as u64 / period,
+             (notifications - previous.notifications) as u64 / period,
+             latency as f64 / 1000.0,
+             statistics.latency_max.load(Ordering::Relaxed) as f64 / 1000.0);
+    *previous = Report {
+        orders: orders,
+        notifications: notifications,
+        latency_total: latency_total,
+        latency_count: latency_count,
+    };
+}
+
+// The fake tokens first, then new players registered through the management API
+fn get_identities(count: usize, management: &str, secret: &str) -> Vec<(Uuid, AuthenticationToken)> {
+    let mut identities: Vec<_> = lycan_serialize::forge_authentication_tokens()
+        .into_iter()
+        .take(count)
+        .collect();
+    let client = hyper::Client::new();
+    while identities.len() < count {
+        let uuid = Uuid::new_v4();
+        let token: String = rand::thread_rng().gen_ascii_chars().take(16).collect();
+        let body = format!(r#"{{"id": "{}", "token": "{}"}}"#, uuid, token);
+        let mut headers = Headers::new();
+        headers.set(ContentType::json());
+        headers.set_raw("Access-Token", vec![secret.as_bytes().to_vec()]);
+        let result = client.post(&format!("{}/connect_character", management))
+            .headers(headers)
+            .body(&body[..])
+            .send();
+        match result {
+            Ok(ref response) if response.status == StatusCode::Ok => {
+                identities.push((uuid, AuthenticationToken(token)));
+            }
+            Ok(response) => {
+                let _ = writeln!(io::stderr(), "Could not register a player: {}", response.status);
+                break;
+            }
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "Could not register a player: {}", e);
+                break;
+            }
+        }
+    }
+    identities
+}
+
+fn run_bot(index: usize,
+           address: &str,
+           uuid: Uuid,
+           token: AuthenticationToken,
+           period: u64,
+           statistics: &Arc<Statistics>,
+           stop: &Arc<AtomicBool>) {
+    let mut stream = match TcpStream::connect(address) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "Bot {}: connection failure: {}", index, e);
+            statistics.connection_failures.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    };
+    let _ = stream.set_nodelay(true);
+    let mut reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "Bot {}: connection failure: {}", index, e);
+            statistics.connection_failures.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    };
+    let entity = match authenticate(&mut stream, &mut reader, uuid, token) {
+        Ok(entity) => entity,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "Bot {}: authentication failure: {}", index, e);
+            statistics.authentication_failures.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    };
+    statistics.connected.fetch_add(1, Ordering::Relaxed);
+
+    // Send time of the say orders that have not come back yet, in ns, by sequence number
+    let pending = Arc::new(Mutex::new(BTreeMap::new()));
+    let disconnected = Arc::new(AtomicBool::new(false));
+    let listener = {
+        let pending = pending.clone();
+        let disconnected = disconnected.clone();
+        let statistics = statistics.clone();
+        thread::spawn(move || {
+            listen(entity, reader, &pending, &statistics);
+            disconnected.store(true, Ordering::Relaxed);
+        })
+    };
+
+    let mut rng = rand::thread_rng();
+    let mut sequence = 0u64;
+    while !stop.load(Ordering::Relaxed) && !disconnected.load(Ordering::Relaxed) {
+        let order = match rng.gen_range(0, 10) {
+            0...3 => {
+                let directions = [Direction::North, Direction::South, Direction::East, Direction::West];
+                Order::Walk(Some(directions[rng.gen_range(0, 4)]))
+            }
+            4 => Order::Walk(None),
+            5...7 => Order::Attack,
+            _ => {
+                sequence += 1;
+                let now = time::precise_time_ns();
+                let mut pending = pending.lock().unwrap();
+                forget_lost_says(&mut pending, now);
+                pending.insert(sequence, now);
+                Order::Say(format!("bot {} {}", index, sequence))
+            }
+        };
+        let command = Command::EntityOrder(EntityOrder {
+            entity: entity,
+            order: order,
+        });
+        if let Err(e) = send_command(&mut stream, &command) {
+            let _ = writeln!(io::stderr(), "Bot {}: could not send an order: {}", index, e);
+            break;
+        }
+        statistics.orders.fetch_add(1, Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(period));
+    }
+    if !stop.load(Ordering::Relaxed) {
+        statistics.disconnections.fetch_add(1, Ordering::Relaxed);
+    }
+    statistics.connected.fetch_sub(1, Ordering::Relaxed);
+    let _ = stream.shutdown(::std::net::Shutdown::Both);
+    let _ = listener.join();
+}
+
+// Returns the id of the entity of the bot
+fn authenticate<R: Read>(stream: &mut TcpStream,
+                         reader: &mut R,
+                         uuid: Uuid,
+                         token: AuthenticationToken,
+                         ) -> Result<u64,String> {
+    let command = Command::GameCommand(GameCommand::Authenticate(uuid, token));
+    try!(send_command(stream, &command));
+    loop {
+        match try!(read_notification(reader)) {
+            Notification::Response { code: ErrorCode::Success } => {}
+            Notification::Response { code } => return Err(format!("server answered {:?}", code)),
+            Notification::ThisIsYou { entity } => return Ok(entity),
+            _ => {}
+        }
+    }
+}
+
+// Counts the notifications, and measures the latency of the say orders of the bot
+fn listen<R: Read>(entity: u64,
+                   mut reader: R,
+                   pending: &Mutex<BTreeMap<u64,u64>>,
+                   statistics: &Statistics) {
+    loop {
+        let notification = match read_notification(&mut reader) {
+            Ok(notification) => notification,
+            Err(_) => return,
+        };
+        statistics.notifications.fetch_add(1, Ordering::Relaxed);
+        if let Notification::Say { entity: speaker, ref message } = notification {
+            if speaker != entity {
+                continue;
+            }
+            let sequence = message.split(' ').last().and_then(|s| s.parse::<u64>().ok());
+            let sent = sequence.and_then(|sequence| pending.lock().unwrap().remove(&sequence));
+            if let Some(sent) = sent {
+                let latency = (time::precise_time_ns() - sent) / 1000;
+                statistics.add_latency(latency as usize);
+            }
+        }
+    }
+}
+
+// The say orders are sent in sequence, so the oldest ones come first
+fn forget_lost_says(pending: &mut BTreeMap<u64,u64>, now: u64) {
+    loop {
+        let oldest = match pending.iter().next() {
+            Some((&sequence, &sent)) if now - sent > SAY_TIMEOUT => sequence,
+            _ => return,
+        };
+        pending.remove(&oldest);
+    }
+}
+
+// Same framing as network::next_message: the length of the message as a little endian u64,
+// then the message
+fn read_notification<R: Read>(reader: &mut R) -> Result<Notification,String> {
+    let size = try!(reader.read_u64::<LittleEndian>().map_err(|e| e.to_string()));
+    Notification::deserialize(reader, size).map_err(|e| format!("{:?}", e))
+}
+
+// The serialization includes the length prefix
+fn send_command<W: Write>(writer: &mut W, command: &Command) -> Result<(),String> {
+    let mut buffer = Vec::with_capacity(128);
+    try!(command.serialize(&mut buffer).map_err(|e| format!("{:?}", e)));
+    writer.write_all(&buffer).map_err(|e| e.to_string())
+}
@@ -0,0 +1,51 @@
+extern crate lycan;
+extern crate env_logger;
+extern crate docopt;
+extern crate rustc_serialize;
+
+use std::io::Write;
+use std::path::Path;
+
+use docopt::Docopt;
+
+use lycan::instance::replay;
+
+static USAGE: &'static str = r#"
+Replays the inputs recorded by an instance, and prints the notifications of each tick in the
+same format as the .notifications file of the recording.
+
+Usage:
+    lycan-replay [options] <recording>
+
+Options:
+    -c URL, --configuration URL     URL of the configuration server, or local directory [default: http://localhost:9000]
+    -t TICKS, --ticks TICKS         Number of ticks to replay, by default until the last recorded input
+    -h, --help                      Prints this message
+"#;
+
+#[derive(RustcDecodable,Debug)]
+struct Args {
+    arg_recording: String,
+    flag_configuration: String,
+    flag_ticks: Option<u64>,
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.decode())
+        .unwrap_or_else(|e| e.exit());
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "warn");
+    }
+    env_logger::init().unwrap();
+    let stdout = std::io::stdout();
+    let mut output = stdout.lock();
+    let result = replay::replay_file(Path::new(&args.arg_recording),
+                                     &args.flag_configuration,
+                                     args.flag_ticks,
+                                     &mut output);
+    if let Err(e) = result {
+        let _ = writeln!(std::io::stderr(), "Error: {}", e);
+        std::process::exit(1);
+    }
+}
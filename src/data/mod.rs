@@ -6,21 +6,45 @@ mod map;
 mod player;
 mod management;
 mod monster;
+mod scripts;
+mod skills;
+mod recording;
 
 pub use self::map::Map;
+pub use self::map::Tile;
 pub use self::management::EntityManagement;
 pub use self::management::EntityType;
 pub use self::management::PositionInstance;
 pub use self::management::PlayerStruct;
 pub use self::management::MonsterStruct;
 pub use self::management::SpawnMonster;
+pub use self::management::Waypoint;
 pub use self::management::ConnectCharacterParam;
 pub use self::management::GetInstances;
 pub use self::management::GetMaps;
+pub use self::management::AiStatistics;
+pub use self::management::AiDebug;
+pub use self::management::NodeStatus;
+pub use self::management::LeafStatus;
+pub use self::management::DebugNode;
+pub use self::management::Blackboard;
+pub use self::management::ThreatEntry;
 pub use self::player::Player;
 pub use self::player::Stats;
+pub use self::player::Resistances;
 pub use self::player::Position;
 pub use self::monster::Monster;
+pub use self::scripts::ScriptManifest;
+pub use self::skills::Skill;
+pub use self::skills::SkillArea;
+pub use self::skills::SkillProjectile;
+pub use self::recording::RecordingHeader;
+pub use self::recording::RecordedInput;
+pub use self::recording::Input;
+pub use self::recording::RecordedPlayer;
+pub use self::recording::RecordedOrder;
+pub use self::recording::RecordedDirection;
+pub use self::recording::RecordedCastTarget;
 
 // XXX: Hack to remove ... currently we consider only one map
 lazy_static!{
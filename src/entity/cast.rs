@@ -0,0 +1,87 @@
+// State of an entity casting skills
+use std::collections::HashMap;
+
+use id::WeakId;
+
+use super::{Entity,Direction};
+
+#[derive(Debug,Clone,Copy)]
+pub enum CastTarget {
+    // Cast in front of the entity
+    Direction(Direction),
+    Entity(WeakId<Entity>),
+}
+
+#[derive(Debug,Clone)]
+pub struct CastOrder {
+    pub skill: String,
+    pub target: CastTarget,
+}
+
+impl CastOrder {
+    pub fn new(skill: String, target: CastTarget) -> CastOrder {
+        CastOrder {
+            skill: skill,
+            target: target,
+        }
+    }
+}
+
+#[derive(Debug,Clone)]
+pub enum CastState {
+    Idle,
+    // Validated against the skill definition during the next tick
+    Requested(CastOrder),
+    Casting {
+        order: CastOrder,
+        // In seconds
+        remaining: f32,
+    },
+}
+
+impl CastState {
+    pub fn is_idle(&self) -> bool {
+        if let CastState::Idle = *self {
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Remaining cooldown of each skill, in seconds
+#[derive(Debug,Clone,Default)]
+pub struct Cooldowns {
+    remaining: HashMap<String, f32>,
+}
+
+impl Cooldowns {
+    pub fn new() -> Cooldowns {
+        Cooldowns {
+            remaining: HashMap::new(),
+        }
+    }
+
+    pub fn start(&mut self, skill: &str, duration: f32) {
+        if duration > 0.0 {
+            self.remaining.insert(String::from(skill), duration);
+        }
+    }
+
+    pub fn is_ready(&self, skill: &str) -> bool {
+        !self.remaining.contains_key(skill)
+    }
+
+    pub fn update(&mut self, tick_duration: f32) {
+        let mut ready = Vec::new();
+        for (skill, remaining) in self.remaining.iter_mut() {
+            *remaining -= tick_duration;
+            if *remaining <= 0.0 {
+                ready.push(skill.clone());
+            }
+        }
+        for skill in ready {
+            self.remaining.remove(&skill);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// Kinds of damage and outcomes of an attack, as declared by the combat script
+use data::Resistances;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum DamageType {
+    Physical,
+    Fire,
+    Ice,
+    Lightning,
+    Poison,
+}
+
+impl DamageType {
+    pub fn from_name(name: &str) -> Option<DamageType> {
+        match name {
+            "physical" => Some(DamageType::Physical),
+            "fire" => Some(DamageType::Fire),
+            "ice" => Some(DamageType::Ice),
+            "lightning" => Some(DamageType::Lightning),
+            "poison" => Some(DamageType::Poison),
+            _ => None,
+        }
+    }
+
+    /// Resistance against this damage type, in percents (capped at 100)
+    pub fn resistance(self, resistances: &Resistances) -> u64 {
+        let resistance = match self {
+            DamageType::Physical => resistances.physical,
+            DamageType::Fire => resistances.fire,
+            DamageType::Ice => resistances.ice,
+            DamageType::Lightning => resistances.lightning,
+            DamageType::Poison => resistances.poison,
+        };
+        if resistance > 100 { 100 } else { resistance }
+    }
+
+    /// Damage actually taken by an entity with the given resistances
+    pub fn mitigate(self, amount: f64, resistances: &Resistances) -> f64 {
+        amount * (100 - self.resistance(resistances)) as f64 / 100.0
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum HitOutcome {
+    Hit,
+    Critical,
+    // The attacker missed its target
+    Miss,
+    // The target avoided the attack
+    Dodge,
+}
+
+impl HitOutcome {
+    pub fn from_name(name: &str) -> Option<HitOutcome> {
+        match name {
+            "critical" => Some(HitOutcome::Critical),
+            "miss" => Some(HitOutcome::Miss),
+            "dodge" => Some(HitOutcome::Dodge),
+            _ => None,
+        }
+    }
+
+    /// If false, the damage declared by the script is discarded
+    pub fn lands(self) -> bool {
+        match self {
+            HitOutcome::Hit | HitOutcome::Critical => true,
+            HitOutcome::Miss | HitOutcome::Dodge => false,
+        }
+    }
+}
@@ -1,39 +1,50 @@
-use std::marker;
+// Mutable access to one entity, along with access to all the others
+//
+// The slice of entities is split around the borrowed entity, so the borrow checker knows that
+// the entity and the others never alias
+use std::slice;
+use std::iter::Chain;
+
+use nalgebra::Point2;
 
 use entity::Entity;
 use id::Id;
 
+use super::spatial::{SpatialIndex,Places};
+
 pub struct OthersAccessor<'a> {
-    inner: &'a mut [Entity],
+    // The entities before and after the borrowed one
+    before: &'a mut [Entity],
+    after: &'a mut [Entity],
+    index: &'a SpatialIndex,
     borrowed_entity_position: usize,
 }
 
 impl <'a> OthersAccessor<'a> {
-    pub fn new(a: &'a mut [Entity], position: usize) -> Option<(&'a mut Entity, OthersAccessor<'a>)> {
-        let entity: &mut Entity = unsafe {
-            match a.get_mut(position) {
-                None => return None,
-                Some(entity) => ::std::mem::transmute(entity),
-            }
-        };
+    pub fn new(a: &'a mut [Entity], index: &'a SpatialIndex, position: usize) -> Option<(&'a mut Entity, OthersAccessor<'a>)> {
+        if position >= a.len() {
+            return None;
+        }
+        let (before, rest) = a.split_at_mut(position);
+        let (entity, after) = rest.split_first_mut().unwrap();
         let wrapper = OthersAccessor {
-            inner: a,
+            before: before,
+            after: after,
+            index: index,
             borrowed_entity_position: position
         };
         Some((entity, wrapper))
     }
 
     pub fn get_by_index(&mut self, index: usize) -> Option<&mut Entity> {
-        if index == self.borrowed_entity_position {
-            None
+        let borrowed = self.borrowed_entity_position;
+        if index < borrowed {
+            self.before.get_mut(index)
+        } else if index > borrowed {
+            self.after.get_mut(index - borrowed - 1)
         } else {
-            self.inner.get_mut(index)
+            None
         }
-        /*
-        let entity = self.inner.get(
-        let a: &mut [T] = unsafe { mem::transmute(self.inner as *mut [T]) };
-        a.get_mut(index)
-        */
     }
 
     pub fn get(&mut self, id: Id<Entity>) -> Option<&mut Entity> {
@@ -44,113 +55,110 @@ impl <'a> OthersAccessor<'a> {
     }
 
     pub fn iter_mut(&mut self) -> OthersIterMut {
-        let p = self.inner.as_mut_ptr();
-        unsafe {
-            OthersIterMut {
-                ptr: p,
-                end: p.offset(self.inner.len() as isize) ,
-                borrowed_entity: p.offset(self.borrowed_entity_position as isize),
-                _marker: marker::PhantomData,
-            }
+        OthersIterMut {
+            inner: self.before.iter_mut().chain(self.after.iter_mut()),
         }
     }
 
     pub fn iter(&self) -> OthersIter {
-        let p = self.inner.as_ptr();
-        unsafe {
-            OthersIter {
-                ptr: p,
-                end: p.offset(self.inner.len() as isize) ,
-                borrowed_entity: p.offset(self.borrowed_entity_position as isize),
-                _marker: marker::PhantomData,
-            }
+        OthersIter {
+            inner: self.before.iter().chain(self.after.iter()),
         }
     }
 
-    // XXX: We should probably have a &self version
     pub fn get_position(&self, id: Id<Entity>) -> Option<usize> {
+        match self.index.place(id.into()) {
+            Some(position) if position != self.borrowed_entity_position => Some(position),
+            _ => None,
+        }
+    }
+
+    /// The other entities closer than `radius` to `center` that pass the filter, in the store
+    /// order
+    pub fn in_range<F>(&self, center: Point2<f32>, radius: f32, mut filter: F) -> Vec<Id<Entity>>
+    where F: FnMut(&Entity) -> bool {
+        self.index.in_range(self, center, radius)
+            .into_iter()
+            .filter_map(|position| self.at(position))
+            .filter(|entity| filter(*entity))
+            .map(|entity| entity.get_id())
+            .collect()
+    }
+
+    /// The closest other entity within `max_distance` of `center` that passes the filter
+    pub fn nearest<F>(&self, center: Point2<f32>, max_distance: f32, filter: F) -> Option<Id<Entity>>
+    where F: FnMut(&Entity) -> bool {
+        self.index.nearest(self, center, max_distance, filter)
+            .and_then(|position| self.at(position))
+            .map(|entity| entity.get_id())
+    }
+
+    /// To add to a range to find all the entities whose hitbox can touch it
+    pub fn hitbox_margin(&self) -> f32 {
+        self.index.max_hitbox_radius()
+    }
+}
+
+// Places are the ones of the store, the borrowed entity is missing
+impl <'a> Places for OthersAccessor<'a> {
+    fn len(&self) -> usize {
+        self.before.len() + 1 + self.after.len()
+    }
+
+    fn at(&self, place: usize) -> Option<&Entity> {
         let borrowed = self.borrowed_entity_position;
-        for (position, entity) in self.iter().enumerate() {
-            if entity.get_id() == id {
-                let adjusted_position = if position >= borrowed {
-                    position + 1
-                } else {
-                    position
-                };
-                return Some(adjusted_position);
-            }
+        if place < borrowed {
+            self.before.get(place)
+        } else if place > borrowed {
+            self.after.get(place - borrowed - 1)
+        } else {
+            None
         }
-        None
     }
 }
 
-// TODO: Have a *const version
 pub struct OthersIterMut<'a> {
-    ptr: *mut Entity,
-    end: *mut Entity,
-    borrowed_entity: *mut Entity,
-    _marker: marker::PhantomData<&'a mut Entity>,
+    inner: Chain<slice::IterMut<'a,Entity>,slice::IterMut<'a,Entity>>,
 }
 
 impl <'a> Iterator for OthersIterMut<'a> {
     type Item = &'a mut Entity;
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        if self.ptr == self.end {
-            None
-        } else {
-            let old = self.ptr;
-            self.ptr = unsafe { self.ptr.offset(1) };
-            if old == self.borrowed_entity {
-                self.next()
-            } else {
-                unsafe { Some(::std::mem::transmute(old)) }
-            }
-        }
+        self.inner.next()
     }
 }
 
 pub struct OthersIter<'a> {
-    ptr: *const Entity,
-    end: *const Entity,
-    borrowed_entity: *const Entity,
-    _marker: marker::PhantomData<&'a Entity>,
+    inner: Chain<slice::Iter<'a,Entity>,slice::Iter<'a,Entity>>,
 }
 
 impl <'a> Iterator for OthersIter<'a> {
     type Item = &'a Entity;
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        if self.ptr == self.end {
-            None
-        } else {
-            let old = self.ptr;
-            self.ptr = unsafe { self.ptr.offset(1) };
-            if old == self.borrowed_entity {
-                self.next()
-            } else {
-                unsafe { Some(::std::mem::transmute(old)) }
-            }
-        }
+        self.inner.next()
     }
 }
 
 pub struct DoubleIterMut<'a> {
     inner: &'a mut [Entity],
+    index: &'a SpatialIndex,
     current_position: usize,
 }
 
 // Cannot implement Iterator because an item borrows the iterator
 impl <'a> DoubleIterMut<'a> {
     pub fn next_item<'b>(&'b mut self) -> Option<(&'b mut Entity, OthersAccessor<'b>)> {
-        let res = OthersAccessor::new(self.inner, self.current_position);
+        let res = OthersAccessor::new(&mut *self.inner, self.index, self.current_position);
         self.current_position += 1;
         res
     }
 
-    pub fn new(e: &mut [Entity]) -> DoubleIterMut {
+    pub fn new(e: &'a mut [Entity], index: &'a SpatialIndex) -> DoubleIterMut<'a> {
         DoubleIterMut {
             inner: e,
+            index: index,
             current_position: 0,
         }
     }
@@ -158,8 +166,18 @@ impl <'a> DoubleIterMut<'a> {
 
 #[cfg(test)]
 mod test {
+    use nalgebra::{Point2,FloatPoint};
+
     use entity::{Entity, EntityStore};
     use id::Id;
+    use tests::fixtures;
+
+    fn store(count: usize) -> (EntityStore, Vec<Id<Entity>>) {
+        let entities: Vec<_> = (0..count).map(|i| fixtures::monster(i as f32, 0.0)).collect();
+        let ids = entities.iter().map(|e| e.get_id()).collect();
+        (fixtures::store(entities), ids)
+    }
+
     #[test]
     fn test() {
         let mut store = EntityStore::new();
@@ -177,5 +195,91 @@ mod test {
             }
         }
     }
-}
 
+    #[test]
+    fn others_are_all_the_other_entities() {
+        let (mut store, ids) = store(4);
+        let mut visited = Vec::new();
+        {
+            let mut double_iter = store.iter_mut_wrapper();
+            while let Some((entity, mut others)) = double_iter.next_item() {
+                visited.push(entity.get_id());
+                let expected: Vec<_> = ids.iter().cloned().filter(|id| *id != entity.get_id()).collect();
+                let found: Vec<_> = others.iter().map(|e| e.get_id()).collect();
+                assert_eq!(found, expected);
+                let found: Vec<_> = others.iter_mut().map(|e| e.get_id()).collect();
+                assert_eq!(found, expected);
+                for (position, id) in ids.iter().enumerate() {
+                    let other = others.get_by_index(position).map(|e| e.get_id());
+                    if *id == entity.get_id() {
+                        assert_eq!(other, None);
+                    } else {
+                        assert_eq!(other, Some(*id));
+                    }
+                }
+                assert!(others.get_by_index(ids.len()).is_none());
+            }
+        }
+        assert_eq!(visited, ids);
+    }
+
+    #[test]
+    fn mutations_reach_the_right_entity() {
+        let (mut store, ids) = store(3);
+        {
+            let (first, mut others) = store.get_mut_wrapper(ids[0]).unwrap();
+            first.pv = 1;
+            others.get(ids[2]).unwrap().pv = 3;
+            for other in others.iter_mut() {
+                other.pv += 10;
+            }
+        }
+        let pvs: Vec<_> = ids.iter().map(|id| store.get(*id).unwrap().get_pv()).collect();
+        assert_eq!(pvs, vec![1, 110, 13]);
+    }
+
+    #[test]
+    fn empty_and_missing() {
+        let (mut store, ids) = store(0);
+        assert!(ids.is_empty());
+        assert!(store.iter_mut_wrapper().next_item().is_none());
+        assert!(store.get_mut_wrapper(Id::<Entity>::forge(42)).is_none());
+    }
+
+    // Entities die in the middle of a tick, and are removed between two accesses
+    #[test]
+    fn removal_during_iteration() {
+        let (mut store, ids) = store(5);
+        for (i, id) in ids.iter().enumerate() {
+            let victim = match store.get_mut_wrapper(*id) {
+                None => {
+                    // Removed by a previous entity
+                    assert!(i % 2 == 1);
+                    continue;
+                }
+                Some((entity, others)) => {
+                    let next = ids.get(i + 1).cloned();
+                    // The index is still consistent with the store
+                    let position = entity.get_position();
+                    let close: Vec<_> = others.iter()
+                        .filter(|e| e.get_position().distance_squared(&position) <= 1.5 * 1.5)
+                        .map(|e| e.get_id())
+                        .collect();
+                    assert_eq!(others.in_range(position, 1.5, |_| true), close);
+                    next.and_then(|next| others.get_position(next).map(|_| next))
+                }
+            };
+            if let Some(victim) = victim {
+                assert!(store.remove(victim).is_some());
+            }
+        }
+        let remaining: Vec<_> = store.iter().map(|e| e.get_id()).collect();
+        assert_eq!(remaining, vec![ids[0], ids[2], ids[4]]);
+        for id in remaining {
+            let (entity, others) = store.get_mut_wrapper(id).unwrap();
+            assert_eq!(entity.get_id(), id);
+            assert_eq!(others.iter().count(), 2);
+            assert!(others.nearest(Point2::new(0.0, 0.0), 10.0, |_| true).is_some());
+        }
+    }
+}
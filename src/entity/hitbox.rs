@@ -1,4 +1,4 @@
-use nalgebra::Point2;
+use nalgebra::{Point2,Vector2,Dot,FloatPoint};
 
 #[derive(Debug,Clone,Copy)]
 pub struct RectangleHitbox {
@@ -39,6 +39,40 @@ impl RectangleHitbox {
         true
     }
 
+    /// First contact of this hitbox, moving in a straight line from `from` to `to`, with the
+    /// other one
+    ///
+    /// Returns the fraction of the movement done at the moment of the contact, None if the
+    /// hitboxes never touch
+    pub fn sweep(&self,
+                 from: Point2<f32>,
+                 to: Point2<f32>,
+                 other: &RectangleHitbox,
+                 pos_other: Point2<f32>,
+                 ) -> Option<f32> {
+        // The moving hitbox is reduced to a point, and the other one grown by its size
+        let half_width = self.half_width + other.half_width;
+        let half_height = self.half_height + other.half_height;
+        let x = slab(from.x, to.x - from.x, pos_other.x - half_width, pos_other.x + half_width);
+        let y = slab(from.y, to.y - from.y, pos_other.y - half_height, pos_other.y + half_height);
+        let ((x_enter, x_exit), (y_enter, y_exit)) = match (x, y) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return None,
+        };
+        let enter = x_enter.max(y_enter).max(0.0);
+        let exit = x_exit.min(y_exit).min(1.0);
+        if enter <= exit {
+            Some(enter)
+        } else {
+            None
+        }
+    }
+
+    /// Radius of the smallest circle around the hitbox
+    pub fn bounding_radius(&self) -> f32 {
+        (self.half_width * self.half_width + self.half_height * self.half_height).sqrt()
+    }
+
     pub fn rotated(&self) -> RectangleHitbox {
         RectangleHitbox {
             half_width: self.half_height,
@@ -46,3 +80,134 @@ impl RectangleHitbox {
         }
     }
 }
+
+// Interval of the movement during which the coordinate is between min and max
+fn slab(origin: f32, delta: f32, min: f32, max: f32) -> Option<(f32,f32)> {
+    if delta == 0.0 {
+        if origin < min || origin > max {
+            None
+        } else {
+            Some((::std::f32::NEG_INFINITY, ::std::f32::INFINITY))
+        }
+    } else {
+        let first = (min - origin) / delta;
+        let second = (max - origin) / delta;
+        if first < second {
+            Some((first, second))
+        } else {
+            Some((second, first))
+        }
+    }
+}
+
+/// Area affected by an attack, from an origin and towards a direction (a unit vector)
+#[derive(Debug,Clone,Copy)]
+pub enum AreaShape {
+    Circle {
+        radius: f32,
+    },
+    Cone {
+        radius: f32,
+        // Total aperture, in radians
+        angle: f32,
+    },
+    Line {
+        length: f32,
+        half_width: f32,
+    },
+}
+
+impl AreaShape {
+    /// Radius of the smallest circle around the origin that contains the area
+    pub fn bounding_radius(&self) -> f32 {
+        match *self {
+            AreaShape::Circle { radius } | AreaShape::Cone { radius, .. } => radius,
+            AreaShape::Line { length, half_width } => {
+                (length * length + half_width * half_width).sqrt()
+            }
+        }
+    }
+
+    pub fn contains(&self, origin: Point2<f32>, direction: Vector2<f32>, point: Point2<f32>) -> bool {
+        let diff = point - origin;
+        match *self {
+            AreaShape::Circle { radius } => {
+                origin.distance_squared(&point) <= radius * radius
+            }
+            AreaShape::Cone { radius, angle } => {
+                let sqdistance = origin.distance_squared(&point);
+                if sqdistance > radius * radius {
+                    return false;
+                }
+                if sqdistance == 0.0 {
+                    return true;
+                }
+                diff.dot(&direction) / sqdistance.sqrt() >= (angle / 2.0).cos()
+            }
+            AreaShape::Line { length, half_width } => {
+                let along = diff.dot(&direction);
+                let across = (diff.x * direction.y - diff.y * direction.x).abs();
+                along >= 0.0 && along <= length && across <= half_width
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::{Point2,Vector2};
+    use std::f32::consts::PI;
+
+    use super::{AreaShape,RectangleHitbox};
+
+    #[test]
+    fn circle() {
+        let shape = AreaShape::Circle { radius: 2.0 };
+        let origin = Point2::new(1.0, 1.0);
+        let direction = Vector2::new(1.0, 0.0);
+        assert!(shape.contains(origin, direction, Point2::new(2.0, 2.0)));
+        assert!(shape.contains(origin, direction, Point2::new(-1.0, 1.0)));
+        assert!(!shape.contains(origin, direction, Point2::new(3.0, 3.0)));
+    }
+
+    #[test]
+    fn cone() {
+        let shape = AreaShape::Cone { radius: 5.0, angle: PI / 2.0 };
+        let origin = Point2::new(0.0, 0.0);
+        let direction = Vector2::new(0.0, 1.0);
+        assert!(shape.contains(origin, direction, Point2::new(0.0, 4.0)));
+        assert!(shape.contains(origin, direction, Point2::new(1.0, 2.0)));
+        assert!(!shape.contains(origin, direction, Point2::new(3.0, 1.0)));
+        assert!(!shape.contains(origin, direction, Point2::new(0.0, -1.0)));
+        assert!(!shape.contains(origin, direction, Point2::new(0.0, 6.0)));
+    }
+
+    #[test]
+    fn line() {
+        let shape = AreaShape::Line { length: 10.0, half_width: 0.5 };
+        let origin = Point2::new(0.0, 0.0);
+        let direction = Vector2::new(-1.0, 0.0);
+        assert!(shape.contains(origin, direction, Point2::new(-9.0, 0.4)));
+        assert!(!shape.contains(origin, direction, Point2::new(-9.0, 1.0)));
+        assert!(!shape.contains(origin, direction, Point2::new(1.0, 0.0)));
+        assert!(!shape.contains(origin, direction, Point2::new(-11.0, 0.0)));
+    }
+
+    #[test]
+    fn sweep() {
+        let projectile = RectangleHitbox::new(0.25, 0.25);
+        let target = RectangleHitbox::new(0.5, 0.5);
+        let position = Point2::new(5.0, 0.0);
+        // Both ends of the movement are clear of the target, but it is crossed in between
+        assert!(!projectile.collision(Point2::new(3.0, 0.0), &target, position));
+        assert!(!projectile.collision(Point2::new(7.0, 0.0), &target, position));
+        let moment = projectile.sweep(Point2::new(3.0, 0.0), Point2::new(7.0, 0.0), &target, position);
+        assert_eq!(moment, Some(0.3125));
+
+        assert_eq!(projectile.sweep(Point2::new(3.0, 1.0), Point2::new(7.0, 1.0), &target, position), None);
+        assert_eq!(projectile.sweep(Point2::new(0.0, 0.0), Point2::new(2.0, 0.0), &target, position), None);
+        // Already touching
+        assert_eq!(projectile.sweep(position, Point2::new(9.0, 0.0), &target, position), Some(0.0));
+        assert_eq!(projectile.sweep(position, position, &target, position), Some(0.0));
+    }
+}
@@ -5,28 +5,40 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use nalgebra::{Point2,Vector2};
 use rand;
 
-use id::{Id,HasForgeableId,HasId};
+use id::{Id,WeakId,HasForgeableId,HasId};
 use data::{
-    Map,Player,Stats,Position,
+    Map,Player,Stats,Position,Resistances,
     EntityManagement, PositionInstance,
     PlayerStruct, MonsterStruct,
     EntityType as DataEntityType,
     Monster,
 };
 use data::UNIQUE_MAP;
-use messages::{EntityState, Notification};
+use messages::{EntityState, Notification, NetworkCastTarget};
 use instance::Instance;
 use actor::ActorId;
 
 use self::hitbox::RectangleHitbox;
+pub use self::hitbox::AreaShape;
+pub use self::status::{Status,StatusKind};
+pub use self::damage::{DamageType,HitOutcome};
+pub use self::cast::{CastOrder,CastTarget};
+pub use self::projectile::{Projectile,Projectiles};
+use self::cast::{CastState,Cooldowns};
+use self::state::{State,Event};
 pub use self::double_iterator::{DoubleIterMut,OthersAccessor,OthersIter,OthersIterMut};
 pub use self::store::EntityStore;
 
 mod status;
+mod damage;
+mod cast;
+mod projectile;
 mod update;
 mod hitbox;
 mod double_iterator;
 mod store;
+mod spatial;
+mod state;
 //mod serialize;
 
 pub use self::update::update;
@@ -37,6 +49,9 @@ pub use lycan_serialize::Direction;
 static DEFAULT_SPEED:    f32 = 10.0;
 static DEFAULT_AI_SPEED: f32 = 5.0;
 static DEFAULT_ATTACK_SPEED: f32 = 2.0; // 2 attacks per seconds
+static DEFAULT_MAX_PV: u64 = 100;
+static MAX_ENERGY: f32 = 100.0;
+static ENERGY_REGENERATION: f32 = 5.0; // Per second
 
 #[derive(Debug)]
 pub struct Entity {
@@ -56,10 +71,16 @@ pub struct Entity {
     attack_offset_y: Vector2<f32>,
     base_stats: Stats,
     stats: CurrentStats,
-
-    // TODO: Replace by a FSM
-    walking: bool,
-    attacking: AttackState,
+    status: Status,
+    // Displacement applied during the next movement resolution
+    knockback: Vector2<f32>,
+    // Consumed by skills
+    energy: f32,
+    cooldowns: Cooldowns,
+    // From 1.0 right after an attack, down to 0.0 when the next one can start
+    attack_reload: Option<f32>,
+
+    state: State,
 }
 
 lazy_static! {
@@ -85,6 +106,11 @@ impl Entity {
                 orientation: orientation,
                 base_stats: base_stats,
                 stats: Default::default(),
+                status: Status::new(),
+                knockback: Vector2::new(0.0, 0.0),
+                energy: MAX_ENERGY,
+                cooldowns: Cooldowns::new(),
+                attack_reload: None,
                 skin: skin,
                 pv: pv,
                 hitbox: RectangleHitbox::new_default(),
@@ -92,8 +118,7 @@ impl Entity {
                 attack_offset_x: Vector2::new(0.75, 0.0),
                 attack_offset_y: Vector2::new(0.0, 1.0),
 
-                walking: false,
-                attacking: AttackState::Idle,
+                state: State::Idle,
             };
             e.recompute_current_stats();
             e
@@ -122,6 +147,7 @@ impl Entity {
             EntityType::Monster(_) => DEFAULT_AI_SPEED,
         };
         self.stats.speed = speed;
+        self.stats.level = self.base_stats.level;
         self.stats.strength = self.base_stats.strength;
         self.stats.dexterity = self.base_stats.dexterity;
         self.stats.constitution = self.base_stats.constitution;
@@ -129,11 +155,14 @@ impl Entity {
         self.stats.precision = self.base_stats.precision;
         self.stats.wisdom = self.base_stats.wisdom;
         self.stats.attack_speed = DEFAULT_ATTACK_SPEED;
+        self.stats.max_pv = DEFAULT_MAX_PV;
+        self.stats.resistances = self.base_stats.resistances;
     }
 
     fn get_attribute(&self, var: &str) -> Option<f64> {
         match var {
             "pv" => Some(self.pv as f64),
+            "max_pv" => Some(self.stats.max_pv as f64),
             "strength" => Some(self.stats.strength as f64),
             "dexterity" => Some(self.stats.dexterity as f64),
             "constitution" => Some(self.stats.constitution as f64),
@@ -141,50 +170,149 @@ impl Entity {
             "precision" => Some(self.stats.precision as f64),
             "wisdom" => Some(self.stats.wisdom as f64),
             "speed" => Some(self.stats.speed as f64),
+            "level" => Some(self.stats.level as f64),
+            "energy" => Some(self.energy as f64),
+            "orientation" => Some(match self.orientation {
+                Direction::North => 0.0,
+                Direction::East => 1.0,
+                Direction::South => 2.0,
+                Direction::West => 3.0,
+            }),
+            "is_player" => Some(if self.is_player() { 1.0 } else { 0.0 }),
+            "experience" => match self.e_type {
+                EntityType::Player(ref player) => Some(player.experience as f64),
+                EntityType::Monster(_) => None,
+            },
+            "stunned" => Some(if self.status.has(StatusKind::Stunned) { 1.0 } else { 0.0 }),
+            "rooted" => Some(if self.status.has(StatusKind::Rooted) { 1.0 } else { 0.0 }),
+            "silenced" => Some(if self.status.has(StatusKind::Silenced) { 1.0 } else { 0.0 }),
+            other if other.starts_with("resistance.") => {
+                DamageType::from_name(&other["resistance.".len()..])
+                    .map(|damage_type| damage_type.resistance(&self.stats.resistances) as f64)
+            }
             _ => None,
         }
     }
 
-    pub fn walk(&mut self, orientation: Option<Direction>) {
+    /// Starts walking in the given direction, or stops
+    ///
+    /// During an attack, the entity walks once it is done. A cast in progress is not
+    /// interrupted, and the order is refused
+    pub fn walk(&mut self, orientation: Option<Direction>) -> Result<(),Error> {
         match orientation {
             Some(o) => {
-                self.walking = true;
+                try!(self.transition(Event::Walk));
                 self.orientation = o;
             }
             None => {
-                self.walking = false;
+                try!(self.transition(Event::Stop));
             }
         }
+        Ok(())
     }
 
-    /// Apply an order to an entity, and optionally returns a notification
-    pub fn apply(&mut self, order: Order) -> Result<Option<Notification>,Error> {
+    /// Apply an order to an entity, and returns the notifications it generates
+    pub fn apply(&mut self, order: Order) -> Result<Vec<Notification>,Error> {
         debug!("Received order {:?}", order);
         match order {
             Order::Walk(orientation) => {
-                match orientation {
-                    None => self.walking = false,
-                    Some(o) => {
-                        self.orientation = o;
-                        self.walking = true;
-                    }
+                let mut notifications = Vec::new();
+                // A player walks away from a cast in progress, an attack still lands
+                if orientation.is_some() && self.state.is_casting() {
+                    notifications.extend(try!(self.interrupt()));
                 }
-                Ok(Some(Notification::walk(self.id.as_u64(), orientation)))
+                try!(self.walk(orientation));
+                notifications.push(Notification::walk(self.id.as_u64(), orientation));
+                Ok(notifications)
             }
             Order::Say(message) => {
-                Ok(Some(Notification::say(self.id.as_u64(), message)))
+                Ok(vec![Notification::say(self.id.as_u64(), message)])
             }
             Order::Attack => {
-                match self.attacking {
-                    AttackState::Idle => {
-                        self.attacking = AttackState::Attacking;
-                        // TODO: Attacking notification
-                        Ok(None)
-                    }
-                    // If the entity was already in the middle of an attack, ignore
-                    AttackState::Attacking => { Err(Error::AlreadyAttacking) }
-                    AttackState::Reloading(_) => { Err(Error::AlreadyAttacking) }
+                let next = try!(self.state.next(Event::Attack));
+                if self.attack_reload.is_some() {
+                    return Err(Error::AlreadyAttacking);
+                }
+                self.state = next;
+                // TODO: Attacking notification
+                Ok(vec![])
+            }
+            Order::Cast { skill, target } => {
+                let target = match target {
+                    NetworkCastTarget::Direction(direction) => CastTarget::Direction(direction),
+                    NetworkCastTarget::Entity(entity) => CastTarget::Entity(WeakId::new(entity)),
+                };
+                try!(self.cast(CastOrder::new(skill, target)));
+                // The clients are notified when the cast starts, once it has been validated
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// Starts casting a skill
+    ///
+    /// The skill, its cost, cooldown and range are checked during the next tick
+    pub fn cast(&mut self, order: CastOrder) -> Result<(),Error> {
+        debug!("Received cast order {:?}", order);
+        if self.status.has(StatusKind::Silenced) {
+            return Err(Error::Silenced);
+        }
+        self.transition(Event::Cast(order))
+    }
+
+    pub fn is_casting(&self) -> bool {
+        self.state.is_casting()
+    }
+
+    /// The state follows the status at the end of the tick
+    pub fn add_status(&mut self, kind: StatusKind, duration: f32) {
+        self.status.apply(kind, duration);
+    }
+
+    // The state is left unchanged if the transition is not legal
+    fn transition(&mut self, event: Event) -> Result<(),Error> {
+        self.state = try!(self.state.next(event));
+        Ok(())
+    }
+
+    // Drops the cast in progress
+    fn interrupt(&mut self) -> Result<Option<Notification>,Error> {
+        let notification = self.cast_interruption();
+        try!(self.transition(Event::Interrupt));
+        Ok(notification)
+    }
+
+    // Only a cast that has started has been announced to the clients
+    fn cast_interruption(&self) -> Option<Notification> {
+        match self.state {
+            State::Casting(CastState::Casting { ref order, .. }) => {
+                Some(Notification::CastInterrupted {
+                    entity: self.id.as_u64(),
+                    skill: order.skill.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    // Enters or leaves the stunned state when the status changes
+    fn follow_status(&mut self) -> Option<Notification> {
+        let stunned = !self.status.can_act();
+        match self.state {
+            State::Dead => None,
+            State::Stunned { .. } => {
+                if !stunned {
+                    let _ = self.transition(Event::Recover);
                 }
+                None
+            }
+            _ => {
+                if !stunned {
+                    return None;
+                }
+                let notification = self.cast_interruption();
+                let _ = self.transition(Event::Stun);
+                notification
             }
         }
     }
@@ -243,6 +371,12 @@ impl Entity {
         self.pv
     }
 
+    /// Back to full health, without any status effect
+    pub fn restore(&mut self) {
+        self.pv = self.stats.max_pv;
+        self.status = Status::new();
+    }
+
     pub fn get_orientation(&self) -> Direction {
         self.orientation
     }
@@ -251,22 +385,33 @@ impl Entity {
         &self.e_type
     }
 
+    pub fn get_status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Gives back the id and skin the entity had in a recording
+    pub fn restore_identity(&mut self, id: Id<Entity>, skin: u64) {
+        self.id = id;
+        self.skin = skin;
+    }
+
+    pub fn get_energy(&self) -> f32 {
+        self.energy
+    }
+
 }
 
 // Reason why an action has been rejected
 // TODO: Put in lycan-serialize
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
 pub enum Error {
     AlreadyAttacking,
-}
-
-#[derive(Debug,Copy,Clone)]
-enum AttackState {
-    Idle,
-    Attacking,
-    // A number between 1.0 and 0.0
-    // 1.0 means the entity just started reloading
-    // When it reaches 0.0 it switches back to the Idle state
-    Reloading(f32),
+    Stunned,
+    Silenced,
+    AlreadyCasting,
+    Dead,
+    // Not a transition of the current state
+    NotAllowed,
 }
 
 #[derive(Debug)]
@@ -288,6 +433,8 @@ struct CurrentStats {
     wisdom: u64,
     speed: f32,
     attack_speed: f32,
+    max_pv: u64,
+    resistances: Resistances,
 }
 
 #[derive(Debug,Clone)]
@@ -315,6 +462,9 @@ impl HasId for Entity {
     type Type = u64;
 }
 
+// Replayed recordings must use the same ids as the original
+impl HasForgeableId for Entity {}
+
 ///////////////////////////////////////////////
 //  Conversions
 //
@@ -343,8 +493,14 @@ impl From<Player> for Entity {
 
 impl Into<Option<Player>> for Entity {
     fn into(self) -> Option<Player> {
+        self.to_player()
+    }
+}
+
+impl Entity {
+    pub fn to_player(&self) -> Option<Player> {
         let player_data = match self.e_type {
-            EntityType::Player(player) => player,
+            EntityType::Player(ref player) => player.clone(),
             _ => {
                 error!("Attempted to convert a non-player entity to a player");
                 return None;
@@ -433,6 +589,7 @@ impl Entity {
             intelligence:   5,
             precision:      6,
             wisdom:         7,
+            resistances:    Default::default(),
         };
         let position = Position {
             x: 0.0,
@@ -464,6 +621,7 @@ impl Entity {
             intelligence:   5,
             precision:      6,
             wisdom:         7,
+            resistances:    Default::default(),
         };
         let skin = NEXT_SKIN.fetch_add(1, Ordering::Relaxed) as u64;
         let monster = MonsterData {
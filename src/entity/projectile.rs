@@ -0,0 +1,93 @@
+// Projectiles launched by entities
+//
+// Projectiles are not entities: they only live for a few ticks, and disappear as soon as they
+// hit an entity or an obstacle
+use nalgebra::{Point2,Vector2};
+
+use id::Id;
+use data::SkillArea;
+use messages::Notification;
+
+use super::Entity;
+use super::hitbox::RectangleHitbox;
+
+#[derive(Debug,Clone)]
+pub struct Projectile {
+    pub id: u64,
+    pub owner: Id<Entity>,
+    pub position: Point2<f32>,
+    pub velocity: Vector2<f32>,
+    // Remaining time before the projectile disappears, in seconds
+    pub lifetime: f32,
+    pub hitbox: RectangleHitbox,
+    // Aariba rule set evaluated on hit, with the owner as source
+    pub effect: String,
+    pub area: SkillArea,
+}
+
+#[derive(Debug,Default)]
+pub struct Projectiles {
+    inner: Vec<Projectile>,
+    next_id: u64,
+}
+
+impl Projectiles {
+    pub fn new() -> Projectiles {
+        Projectiles {
+            inner: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn spawn(&mut self,
+                 owner: Id<Entity>,
+                 position: Point2<f32>,
+                 velocity: Vector2<f32>,
+                 lifetime: f32,
+                 size: f32,
+                 effect: String,
+                 area: SkillArea,
+                 notifications: &mut Vec<Notification>,
+                 ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        notifications.push(Notification::ProjectileSpawned {
+            projectile: id,
+            owner: owner.as_u64(),
+            position: position,
+            velocity: velocity,
+        });
+        self.inner.push(Projectile {
+            id: id,
+            owner: owner,
+            position: position,
+            velocity: velocity,
+            lifetime: lifetime,
+            hitbox: RectangleHitbox::new(size, size),
+            effect: effect,
+            area: area,
+        });
+        id
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<Projectile> {
+        self.inner.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Projectile> {
+        self.inner.iter_mut().find(|p| p.id == id)
+    }
+
+    pub fn despawn(&mut self, id: u64, notifications: &mut Vec<Notification>) {
+        if let Some(position) = self.inner.iter().position(|p| p.id == id) {
+            self.inner.remove(position);
+            notifications.push(Notification::ProjectileDespawned {
+                projectile: id,
+            });
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// Indexes of the entities of a store
+//
+// The place of each entity in the store is kept by id, and their positions are kept in a
+// uniform grid of square cells of CELL_SIZE. A range query only looks at the entities of the
+// cells that overlap the range, or at all the entities when there are fewer of them than cells
+// to look at.
+//
+// The results always follow the order of the store, so that using the index does not change
+// the outcome of the simulation
+use std::collections::HashMap;
+use std::cmp::Ordering;
+
+use nalgebra::{Point2,FloatPoint};
+
+use id::{Id,WeakId};
+use super::Entity;
+
+pub static CELL_SIZE: f32 = 5.0;
+
+type Cell = (i32,i32);
+
+/// Entities found by their place in the store
+pub trait Places {
+    fn len(&self) -> usize;
+    fn at(&self, place: usize) -> Option<&Entity>;
+}
+
+impl Places for [Entity] {
+    fn len(&self) -> usize {
+        <[Entity]>::len(self)
+    }
+
+    fn at(&self, place: usize) -> Option<&Entity> {
+        self.get(place)
+    }
+}
+
+#[derive(Debug)]
+pub struct SpatialIndex {
+    // Place of each entity in the store
+    places: HashMap<WeakId<Entity>,usize>,
+    cells: HashMap<Cell,Vec<Id<Entity>>>,
+    cell_of: HashMap<WeakId<Entity>,Cell>,
+    // Largest bounding radius of the hitboxes, to find the entities whose hitbox can touch
+    // an area
+    max_hitbox_radius: f32,
+}
+
+impl SpatialIndex {
+    pub fn new() -> SpatialIndex {
+        SpatialIndex {
+            places: HashMap::new(),
+            cells: HashMap::new(),
+            cell_of: HashMap::new(),
+            max_hitbox_radius: 0.0,
+        }
+    }
+
+    pub fn insert(&mut self, entity: &Entity, place: usize) {
+        let id = entity.get_id();
+        self.places.insert(id.into(), place);
+        self.add_to_cell(id, cell(entity.position));
+        let radius = entity.hitbox.bounding_radius();
+        if radius > self.max_hitbox_radius {
+            self.max_hitbox_radius = radius;
+        }
+    }
+
+    /// Removes an entity removed from the store, `shifted` are the entities that followed it
+    pub fn remove(&mut self, entity: &Entity, shifted: &[Entity]) {
+        let id = entity.get_id();
+        if let Some(place) = self.places.remove(&WeakId::from(id)) {
+            for (offset, entity) in shifted.iter().enumerate() {
+                self.places.insert(entity.get_id().into(), place + offset);
+            }
+        }
+        self.remove_from_cell(id);
+    }
+
+    /// Must be called every time the entity moves
+    pub fn moved(&mut self, entity: &Entity) {
+        let id = entity.get_id();
+        let new_cell = cell(entity.position);
+        if self.cell_of.get(&WeakId::from(id)) == Some(&new_cell) {
+            return;
+        }
+        self.remove_from_cell(id);
+        self.add_to_cell(id, new_cell);
+    }
+
+    pub fn place(&self, id: WeakId<Entity>) -> Option<usize> {
+        self.places.get(&id).cloned()
+    }
+
+    pub fn max_hitbox_radius(&self) -> f32 {
+        self.max_hitbox_radius
+    }
+
+    /// Places of the entities closer than `radius` to `center`, in increasing order
+    pub fn in_range<P>(&self,
+                       entities: &P,
+                       center: Point2<f32>,
+                       radius: f32,
+                       ) -> Vec<usize>
+    where P: Places + ?Sized {
+        let sqradius = radius * radius;
+        // At most this many cells on each side, estimated before any conversion to cell
+        // coordinates, which would overflow for a very large or infinite radius
+        let side = (2.0 * radius as f64 / CELL_SIZE as f64).floor() + 2.0;
+        let mut places: Vec<usize> = if !(side * side <= entities.len() as f64) {
+            (0..entities.len()).collect()
+        } else {
+            let (min_x, min_y) = cell(Point2::new(center.x - radius, center.y - radius));
+            let (max_x, max_y) = cell(Point2::new(center.x + radius, center.y + radius));
+            let mut places = Vec::new();
+            for x in min_x..(max_x + 1) {
+                for y in min_y..(max_y + 1) {
+                    if let Some(ids) = self.cells.get(&(x, y)) {
+                        places.extend(ids.iter().filter_map(|id| self.place((*id).into())));
+                    }
+                }
+            }
+            places.sort();
+            places
+        };
+        places.retain(|place| {
+            match entities.at(*place) {
+                Some(entity) => entity.position.distance_squared(&center) <= sqradius,
+                None => false,
+            }
+        });
+        places
+    }
+
+    /// Place of the closest entity within `max_distance` of `center` that passes the filter
+    ///
+    /// Between entities at the same distance, the first one of the store is chosen
+    pub fn nearest<P,F>(&self,
+                        entities: &P,
+                        center: Point2<f32>,
+                        max_distance: f32,
+                        mut filter: F,
+                        ) -> Option<usize>
+    where P: Places + ?Sized,
+          F: FnMut(&Entity) -> bool {
+        let mut candidates: Vec<(f32,usize,&Entity)> = self.in_range(entities, center, max_distance)
+            .into_iter()
+            .filter_map(|place| entities.at(place).map(|entity| (place, entity)))
+            .map(|(place, entity)| (entity.position.distance_squared(&center), place, entity))
+            .collect();
+        candidates.sort_by(|a, b| {
+            match a.0.partial_cmp(&b.0) {
+                Some(Ordering::Equal) | None => a.1.cmp(&b.1),
+                Some(ordering) => ordering,
+            }
+        });
+        candidates.into_iter()
+            .find(|&(_, _, entity)| filter(entity))
+            .map(|(_, place, _)| place)
+    }
+
+    fn add_to_cell(&mut self, id: Id<Entity>, cell: Cell) {
+        self.cells.entry(cell).or_insert_with(Vec::new).push(id);
+        self.cell_of.insert(id.into(), cell);
+    }
+
+    fn remove_from_cell(&mut self, id: Id<Entity>) {
+        let cell = match self.cell_of.remove(&WeakId::from(id)) {
+            Some(cell) => cell,
+            None => return,
+        };
+        let empty = match self.cells.get_mut(&cell) {
+            Some(ids) => {
+                ids.retain(|other| *other != id);
+                ids.is_empty()
+            }
+            None => false,
+        };
+        if empty {
+            self.cells.remove(&cell);
+        }
+    }
+}
+
+fn cell(position: Point2<f32>) -> Cell {
+    ((position.x / CELL_SIZE).floor() as i32,
+     (position.y / CELL_SIZE).floor() as i32)
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::{Point2,FloatPoint};
+
+    use id::Id;
+    use entity::{Entity,EntityStore};
+    use tests::fixtures::{monster,store};
+
+    // The same query, without the index
+    fn linear_range(store: &EntityStore, center: Point2<f32>, radius: f32) -> Vec<Id<Entity>> {
+        store.iter()
+            .filter(|e| e.get_position().distance_squared(&center) <= radius * radius)
+            .map(|e| e.get_id())
+            .collect()
+    }
+
+    #[test]
+    fn range_follows_movements() {
+        let mut store = EntityStore::new();
+        let mut ids = Vec::new();
+        for i in 0..50 {
+            let entity = monster((i % 10) as f32 * 3.0, (i / 10) as f32 * 4.0);
+            ids.push(entity.get_id());
+            store.push(entity);
+        }
+        let center = Point2::new(10.0, 8.0);
+        assert_eq!(store.in_range(center, 6.0, |_| true), linear_range(&store, center, 6.0));
+
+        for (i, id) in ids.iter().enumerate() {
+            store.move_entity(*id, |entity| {
+                entity.position = Point2::new(entity.position.y * 2.0, i as f32);
+            });
+        }
+        assert_eq!(store.in_range(center, 6.0, |_| true), linear_range(&store, center, 6.0));
+        // Larger than the store, all the entities are checked
+        assert_eq!(store.in_range(center, 100.0, |_| true), linear_range(&store, center, 100.0));
+    }
+
+    #[test]
+    fn removal_keeps_places() {
+        let mut store = EntityStore::new();
+        let first = monster(0.0, 0.0);
+        let second = monster(1.0, 0.0);
+        let third = monster(2.0, 0.0);
+        let (first_id, second_id, third_id) = (first.get_id(), second.get_id(), third.get_id());
+        store.push(first);
+        store.push(second);
+        store.push(third);
+
+        assert!(store.remove(second_id).is_some());
+        assert!(store.get(second_id).is_none());
+        assert_eq!(store.get(third_id).map(|e| e.get_id()), Some(third_id));
+        assert_eq!(store.in_range(Point2::new(0.0, 0.0), 5.0, |_| true), vec![first_id, third_id]);
+    }
+
+    #[test]
+    fn infinite_radius() {
+        let entities: Vec<_> = (0..20).map(|i| monster(i as f32 * 1000.0, -(i as f32) * 1000.0)).collect();
+        let ids: Vec<_> = entities.iter().map(|e| e.get_id()).collect();
+        let store = store(entities);
+        let center = Point2::new(0.0, 0.0);
+        assert_eq!(store.in_range(center, ::std::f32::INFINITY, |_| true), ids);
+        assert_eq!(store.in_range(center, ::std::f32::MAX, |_| true), ids);
+        assert_eq!(store.nearest(center, ::std::f32::INFINITY, |_| true), Some(ids[0]));
+    }
+
+    #[test]
+    fn nearest() {
+        let mut store = EntityStore::new();
+        let far = monster(4.0, 0.0);
+        let close = monster(-2.0, 0.0);
+        let tied = monster(0.0, 2.0);
+        let (far_id, close_id, tied_id) = (far.get_id(), close.get_id(), tied.get_id());
+        store.push(far);
+        store.push(close);
+        store.push(tied);
+
+        let center = Point2::new(0.0, 0.0);
+        assert_eq!(store.nearest(center, 10.0, |_| true), Some(close_id));
+        assert_eq!(store.nearest(center, 10.0, |e| e.get_id() != close_id), Some(tied_id));
+        assert_eq!(store.nearest(center, 10.0, |e| e.get_id() == far_id), Some(far_id));
+        assert_eq!(store.nearest(center, 3.0, |e| e.get_id() == far_id), None);
+    }
+}
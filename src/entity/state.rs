@@ -0,0 +1,193 @@
+// State machine of an entity
+//
+// An entity is in exactly one state at a time. The orders it receives and the events of the
+// simulation are transitions, which are refused when they are not legal in the current state:
+// an entity cannot attack while casting, nor do anything while stunned or dead.
+//
+// An entity stands still while it attacks or is stunned, but it remembers whether it was
+// walking, and walks again in the same direction once the attack is done or the stun has
+// expired. Walking orders given during an attack only change what happens after it.
+//
+// Moving while casting is not a legal transition, the cast has to be interrupted first.
+// Orders from a client do it, so that a player can always walk away, while AIs finish what
+// they have started
+use super::Error;
+use super::cast::{CastOrder,CastState};
+
+#[derive(Debug,Clone)]
+pub enum State {
+    Idle,
+    Walking,
+    // The attack lands during the next tick, then the entity walks again if `walking`
+    Attacking { walking: bool },
+    // Never holds CastState::Idle
+    Casting(CastState),
+    // As long as the entity has the stunned status, then it walks again if `walking`
+    Stunned { walking: bool },
+    // Killed during this tick, and removed at the end of it
+    Dead,
+}
+
+#[derive(Debug,Clone)]
+pub enum Event {
+    Walk,
+    Stop,
+    Attack,
+    Cast(CastOrder),
+    // The attack or the cast has been resolved
+    Done,
+    // The attack or the cast is dropped before being resolved
+    Interrupt,
+    Stun,
+    // The stun has expired
+    Recover,
+    Die,
+}
+
+impl State {
+    /// The state after the event, or the reason why the event is not legal in this state
+    pub fn next(&self, event: Event) -> Result<State,Error> {
+        match (self, event) {
+            (&State::Dead, _) => Err(Error::Dead),
+            (_, Event::Die) => Ok(State::Dead),
+
+            (&State::Stunned { walking }, Event::Recover) => Ok(State::resume(walking)),
+            // A stunned entity cannot start walking, but it can forget about it
+            (&State::Stunned { .. }, Event::Stop) => Ok(State::Stunned { walking: false }),
+            (&State::Stunned { .. }, _) => Err(Error::Stunned),
+            (&State::Walking, Event::Stun) => Ok(State::Stunned { walking: true }),
+            (&State::Attacking { walking }, Event::Stun) => Ok(State::Stunned { walking: walking }),
+            (_, Event::Stun) => Ok(State::Stunned { walking: false }),
+
+            (&State::Idle, Event::Walk) | (&State::Walking, Event::Walk) => Ok(State::Walking),
+            (&State::Idle, Event::Stop) | (&State::Walking, Event::Stop) => Ok(State::Idle),
+            (&State::Idle, Event::Attack) => Ok(State::Attacking { walking: false }),
+            (&State::Walking, Event::Attack) => Ok(State::Attacking { walking: true }),
+            (&State::Idle, Event::Cast(order)) | (&State::Walking, Event::Cast(order)) => {
+                Ok(State::Casting(CastState::Requested(order)))
+            }
+
+            (&State::Attacking { .. }, Event::Walk) => Ok(State::Attacking { walking: true }),
+            (&State::Attacking { .. }, Event::Stop) => Ok(State::Attacking { walking: false }),
+            (&State::Attacking { walking }, Event::Done) => Ok(State::resume(walking)),
+            (&State::Attacking { .. }, Event::Interrupt) => Ok(State::Idle),
+            (&State::Attacking { .. }, _) => Err(Error::AlreadyAttacking),
+
+            (&State::Casting(ref cast), Event::Stop) => Ok(State::Casting(cast.clone())),
+            (&State::Casting(_), Event::Done) | (&State::Casting(_), Event::Interrupt) => Ok(State::Idle),
+            (&State::Casting(_), _) => Err(Error::AlreadyCasting),
+
+            (_, Event::Done) | (_, Event::Interrupt) | (_, Event::Recover) => Err(Error::NotAllowed),
+        }
+    }
+
+    // After an attack or a stun
+    fn resume(walking: bool) -> State {
+        if walking {
+            State::Walking
+        } else {
+            State::Idle
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        if let State::Idle = *self {
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_walking(&self) -> bool {
+        if let State::Walking = *self {
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_attacking(&self) -> bool {
+        if let State::Attacking { .. } = *self {
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_casting(&self) -> bool {
+        if let State::Casting(_) = *self {
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use entity::Error;
+    use entity::cast::{CastOrder,CastTarget};
+    use lycan_serialize::Direction;
+    use super::{State,Event};
+
+    fn cast() -> Event {
+        Event::Cast(CastOrder::new(String::from("fireball"), CastTarget::Direction(Direction::North)))
+    }
+
+    fn after(events: Vec<Event>) -> Result<State,Error> {
+        let mut state = State::Idle;
+        for event in events {
+            state = try!(state.next(event));
+        }
+        Ok(state)
+    }
+
+    #[test]
+    fn attack_and_cast_are_exclusive() {
+        assert!(after(vec![Event::Walk, Event::Attack]).unwrap().is_attacking());
+        assert_eq!(after(vec![Event::Attack, Event::Attack]).unwrap_err(), Error::AlreadyAttacking);
+        assert_eq!(after(vec![Event::Attack, cast()]).unwrap_err(), Error::AlreadyAttacking);
+        assert_eq!(after(vec![cast(), Event::Attack]).unwrap_err(), Error::AlreadyCasting);
+        assert_eq!(after(vec![cast(), cast()]).unwrap_err(), Error::AlreadyCasting);
+        assert!(after(vec![Event::Attack, Event::Done, cast()]).unwrap().is_casting());
+    }
+
+    #[test]
+    fn walking_needs_an_interruption() {
+        assert_eq!(after(vec![cast(), Event::Walk]).unwrap_err(), Error::AlreadyCasting);
+        assert!(after(vec![cast(), Event::Stop]).unwrap().is_casting());
+        assert!(after(vec![cast(), Event::Interrupt, Event::Walk]).unwrap().is_walking());
+        assert!(after(vec![Event::Attack, Event::Interrupt, Event::Walk]).unwrap().is_walking());
+        assert!(after(vec![Event::Attack, Event::Interrupt]).unwrap().is_idle());
+        assert_eq!(after(vec![Event::Walk, Event::Interrupt]).unwrap_err(), Error::NotAllowed);
+    }
+
+    #[test]
+    fn walking_resumes_after_an_attack() {
+        assert!(after(vec![Event::Walk, Event::Attack, Event::Done]).unwrap().is_walking());
+        assert!(after(vec![Event::Attack, Event::Done]).unwrap().is_idle());
+        // The orders given during the attack apply after it
+        assert!(after(vec![Event::Walk, Event::Attack, Event::Walk]).unwrap().is_attacking());
+        assert!(after(vec![Event::Attack, Event::Walk, Event::Done]).unwrap().is_walking());
+        assert!(after(vec![Event::Walk, Event::Attack, Event::Stop, Event::Done]).unwrap().is_idle());
+    }
+
+    #[test]
+    fn walking_resumes_after_a_stun() {
+        assert!(after(vec![Event::Walk, Event::Stun, Event::Recover]).unwrap().is_walking());
+        assert!(after(vec![Event::Stun, Event::Recover]).unwrap().is_idle());
+        assert!(after(vec![Event::Walk, Event::Attack, Event::Stun, Event::Recover]).unwrap().is_walking());
+        assert!(after(vec![Event::Walk, Event::Stun, Event::Stop, Event::Recover]).unwrap().is_idle());
+        // A cast is not resumed, and neither is the walk before it
+        assert!(after(vec![Event::Walk, cast(), Event::Stun, Event::Recover]).unwrap().is_idle());
+    }
+
+    #[test]
+    fn stunned_and_dead() {
+        assert!(after(vec![cast(), Event::Stun]).is_ok());
+        assert_eq!(after(vec![Event::Stun, Event::Walk]).unwrap_err(), Error::Stunned);
+        assert!(after(vec![Event::Walk, Event::Stun, Event::Recover, Event::Attack]).unwrap().is_attacking());
+        assert_eq!(after(vec![Event::Die, Event::Walk]).unwrap_err(), Error::Dead);
+        assert_eq!(after(vec![Event::Die, Event::Recover]).unwrap_err(), Error::Dead);
+    }
+}
@@ -1,8 +1,74 @@
 
-
 // List of status currently affecting an entity
 // The status can be things like rooted, stunned, silenced ...
-// 
-// NOTE: We will probably need to keep a refcount of effects 
-// producing these status, to know when to remove it
-struct Status;
+//
+// Each status has a remaining duration in seconds. Applying a status that is already
+// present keeps the longest duration
+#[derive(Debug,Clone,Default)]
+pub struct Status {
+    effects: Vec<(StatusKind, f32)>,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum StatusKind {
+    // Cannot move nor act
+    Stunned,
+    // Cannot move, but can act
+    Rooted,
+    // Cannot cast spells
+    Silenced,
+}
+
+impl StatusKind {
+    pub fn from_name(name: &str) -> Option<StatusKind> {
+        match name {
+            "stun" => Some(StatusKind::Stunned),
+            "root" => Some(StatusKind::Rooted),
+            "silence" => Some(StatusKind::Silenced),
+            _ => None,
+        }
+    }
+}
+
+impl Status {
+    pub fn new() -> Status {
+        Status {
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, kind: StatusKind, duration: f32) {
+        if duration <= 0.0 {
+            return;
+        }
+        for effect in self.effects.iter_mut() {
+            if effect.0 == kind {
+                if effect.1 < duration {
+                    effect.1 = duration;
+                }
+                return;
+            }
+        }
+        self.effects.push((kind, duration));
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|effect| effect.0 == kind)
+    }
+
+    pub fn can_move(&self) -> bool {
+        !self.has(StatusKind::Stunned) && !self.has(StatusKind::Rooted)
+    }
+
+    pub fn can_act(&self) -> bool {
+        !self.has(StatusKind::Stunned)
+    }
+
+    /// Decreases the remaining durations, and removes expired status
+    pub fn update(&mut self, tick_duration: f32) {
+        for effect in self.effects.iter_mut() {
+            effect.1 -= tick_duration;
+        }
+        self.effects.retain(|effect| effect.1 > 0.0);
+    }
+}
@@ -1,31 +1,35 @@
+use nalgebra::Point2;
+
 use id::{Id, WeakId};
 use super::Entity;
 use super::{OthersAccessor,DoubleIterMut};
+use super::spatial::SpatialIndex;
 
 // Abstraction so that if we change the implementation it doesn't affect the rest
+//
+// The entities are kept in a vector, whose order is the order of iteration. The index finds an
+// entity from its id, and the entities around a position
 #[derive(Debug)]
 pub struct EntityStore {
     entities: Vec<Entity>,
+    index: SpatialIndex,
 }
 
 impl EntityStore {
     pub fn new() -> EntityStore {
         EntityStore {
             entities: Vec::new(),
+            index: SpatialIndex::new(),
         }
     }
 
     pub fn push(&mut self, entity: Entity) {
+        self.index.insert(&entity, self.entities.len());
         self.entities.push(entity)
     }
 
     pub fn remove<T: Into<WeakId<Entity>>>(&mut self, id: T) -> Option<Entity> {
-        let position = match self.get_position(id.into()) {
-            Some(pos) => pos,
-            None => return None,
-        };
-
-        Some(self.entities.remove(position))
+        self.remove_if(id, |_| true)
     }
 
     pub fn remove_if<T,F>(&mut self, id: T, f: F) -> Option<Entity>
@@ -37,7 +41,9 @@ impl EntityStore {
         };
 
         if f(self.entities.get(position).unwrap()) {
-            Some(self.entities.remove(position))
+            let entity = self.entities.remove(position);
+            self.index.remove(&entity, &self.entities[position..]);
+            Some(entity)
         } else {
             None
         }
@@ -47,35 +53,65 @@ impl EntityStore {
         self.get_position(id.into()).map(move |position| self.entities.get(position).unwrap())
     }
 
+    /// The position of the entity must not be changed, see `move_entity`
     pub fn get_mut<T: Into<WeakId<Entity>>>(&mut self, id: T) -> Option<&mut Entity> {
         self.get_position(id.into()).map(move |position| self.entities.get_mut(position).unwrap())
     }
 
+    /// Gives access to an entity whose position can change, and keeps the index up to date
+    pub fn move_entity<T,F>(&mut self, id: T, f: F) -> bool
+    where T: Into<WeakId<Entity>>,
+          F: FnOnce(&mut Entity) {
+        let position = match self.get_position(id.into()) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let entity = &mut self.entities[position];
+        f(entity);
+        self.index.moved(entity);
+        true
+    }
+
     pub fn get_mut_wrapper<'a,T: Into<WeakId<Entity>>>(&'a mut self, id: T) -> Option<(&'a mut Entity, OthersAccessor<'a>)> {
-        self.get_position(id.into()).map(move |position| {
-            OthersAccessor::new(&mut self.entities, position).unwrap()
-        })
+        let position = match self.get_position(id.into()) {
+            Some(pos) => pos,
+            None => return None,
+        };
+        OthersAccessor::new(&mut self.entities, &self.index, position)
     }
 
     fn get_position(&self, id: WeakId<Entity>) -> Option<usize> {
-        for (position, entity) in self.entities.iter().enumerate() {
-            if WeakId::from(entity.get_id()) == id {
-                return Some(position);
-            }
-        }
-        None
+        self.index.place(id)
+    }
+
+    /// The entities closer than `radius` to `center` that pass the filter, in the store order
+    pub fn in_range<F>(&self, center: Point2<f32>, radius: f32, mut filter: F) -> Vec<Id<Entity>>
+    where F: FnMut(&Entity) -> bool {
+        self.index.in_range(&self.entities[..], center, radius)
+            .into_iter()
+            .map(|place| &self.entities[place])
+            .filter(|entity| filter(*entity))
+            .map(|entity| entity.get_id())
+            .collect()
+    }
+
+    /// The closest entity within `max_distance` of `center` that passes the filter
+    pub fn nearest<F>(&self, center: Point2<f32>, max_distance: f32, filter: F) -> Option<Id<Entity>>
+    where F: FnMut(&Entity) -> bool {
+        self.index.nearest(&self.entities[..], center, max_distance, filter)
+            .map(|place| self.entities[place].get_id())
     }
 
     pub fn iter(&self) -> ::std::slice::Iter<Entity> {
         self.entities.iter()
     }
 
+    /// The positions of the entities must not be changed, see `move_entity`
     pub fn iter_mut(&mut self) -> ::std::slice::IterMut<Entity> {
         self.entities.iter_mut()
     }
 
     pub fn iter_mut_wrapper(&mut self) -> DoubleIterMut {
-        DoubleIterMut::new(&mut self.entities)
+        DoubleIterMut::new(&mut self.entities, &self.index)
     }
 }
-
@@ -0,0 +1,103 @@
+// Resolution of area of effect attacks
+//
+// All the entities in the area, and in sight of its origin, are affected by the effect script
+use nalgebra::{Point2,Vector2};
+
+use aariba::rules::RulesEvaluator;
+
+use id::{Id,WeakId};
+use data::SkillArea;
+use entity::{
+    Entity,
+    OthersAccessor,
+    AreaShape,
+};
+use messages::Notification;
+use instance::InstanceRng;
+use instance::geometry::MapGeometry;
+
+use super::effects;
+
+/// Evaluates the effect script on all the entities in the shape
+///
+/// The source is never affected
+pub fn resolve_area(
+    script: &RulesEvaluator,
+    shape: AreaShape,
+    origin: Point2<f32>,
+    direction: Vector2<f32>,
+    source: &mut Entity,
+    others: &mut OthersAccessor,
+    geometry: &MapGeometry,
+    notifications: &mut Vec<Notification>,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    ) {
+    let affected = others.in_range(origin, shape.bounding_radius(), |e| {
+        !dead_entities_id.contains(&e.id) &&
+            shape.contains(origin, direction, e.position) &&
+            geometry.has_line_of_sight(origin, e.position)
+    });
+    for id in affected {
+        // An entity can be killed by the effect on a previous one
+        if dead_entities_id.contains(&id) {
+            continue;
+        }
+        if let Some(target) = others.get(id) {
+            effects::apply_effect(script, source, target, notifications, dead_entities_id, rng);
+        }
+    }
+}
+
+/// Applies the effect of a skill
+///
+/// `origin` is where the skill comes from (the caster or the impact of a projectile), `center`
+/// the targeted point and `direction` the direction of the skill. With a `Target` area, only
+/// `target` is affected
+pub fn apply_skill_area(
+    script: &RulesEvaluator,
+    area: SkillArea,
+    origin: Point2<f32>,
+    center: Point2<f32>,
+    direction: Vector2<f32>,
+    target: Option<WeakId<Entity>>,
+    source: &mut Entity,
+    others: &mut OthersAccessor,
+    geometry: &MapGeometry,
+    notifications: &mut Vec<Notification>,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    ) {
+    let (shape, origin) = match area {
+        SkillArea::Target => {
+            let target = target.and_then(|target| {
+                others.iter().find(|e| WeakId::from(e.id) == target).map(|e| e.id)
+            });
+            if let Some(target) = target {
+                if !dead_entities_id.contains(&target) {
+                    if let Some(target) = others.get(target) {
+                        effects::apply_effect(script, source, target, notifications, dead_entities_id, rng);
+                    }
+                }
+            }
+            return;
+        }
+        SkillArea::Circle { radius } => (AreaShape::Circle { radius: radius }, center),
+        SkillArea::Cone { radius, angle } => {
+            (AreaShape::Cone { radius: radius, angle: angle.to_radians() }, origin)
+        }
+        SkillArea::Line { length, width } => {
+            (AreaShape::Line { length: length, half_width: width / 2.0 }, origin)
+        }
+    };
+    resolve_area(script,
+                 shape,
+                 origin,
+                 direction,
+                 source,
+                 others,
+                 geometry,
+                 notifications,
+                 dead_entities_id,
+                 rng);
+}
@@ -0,0 +1,330 @@
+// Integration of the aariba scripts with the entities
+//
+// An effect script is evaluated with a source and a target entity, and can read and modify
+// their attributes. This is used by attacks and skills
+use std::cell::RefCell;
+
+use nalgebra::{Vector2,FloatPoint};
+use rand::Rng;
+
+use aariba::expressions::{Store};
+use aariba::rules::RulesEvaluator;
+
+use id::Id;
+use entity::{
+    Entity,
+    EntityType,
+    StatusKind,
+    DamageType,
+    HitOutcome,
+};
+use entity::state::Event;
+use messages::Notification;
+use instance::InstanceRng;
+
+/// Evaluates the effect script, source and target must be different entities
+pub fn apply_effect(
+    script: &RulesEvaluator,
+    source: &mut Entity,
+    target: &mut Entity,
+    notifications: &mut Vec<Notification>,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    ) {
+    let mut integration = AaribaIntegration::new(source,
+                                                 target,
+                                                 notifications,
+                                                 dead_entities_id,
+                                                 rng,
+                                                 );
+    match script.evaluate(&mut integration) {
+        Ok(()) => integration.apply_damages(),
+        Err(e) => error!("Script error: {:#?}", e),
+    }
+}
+
+struct AaribaIntegration<'a,'b, 'c, 'd, 'e> {
+    source: &'a mut Entity,
+    target: &'b mut Entity,
+    notifications: &'c mut Vec<Notification>,
+    dead_entities_id: &'d mut Vec<Id<Entity>>,
+    // Attributes are read through a shared reference, but `$random` draws from the generator
+    rng: RefCell<&'e mut InstanceRng>,
+    // Declared by the script, applies to all the damage dealt during the evaluation
+    outcome: HitOutcome,
+    // Damage is only applied once the script has been fully evaluated, when the outcome is known
+    damages: Vec<(Side, DamageType, f64)>,
+}
+
+#[derive(Debug,Clone,Copy)]
+enum Side {
+    Source,
+    Target,
+}
+
+impl <'a, 'b, 'c, 'd, 'e> Store for AaribaIntegration<'a, 'b, 'c, 'd, 'e> {
+    fn get_attribute(&self, var: &str) -> Option<f64> {
+        let mut splitn = var.splitn(2, '.');
+        let first = match splitn.next() {
+            Some(first) => first,
+            None => return None,
+        };
+        let second = match splitn.next() {
+            Some(s) => s,
+            None => {
+                return match first {
+                    "distance" => {
+                        Some(self.source.position.distance(&self.target.position) as f64)
+                    }
+                    // Uniform in [0, 1), drawn from the generator of the instance
+                    "random" => Some(self.rng.borrow_mut().gen::<f64>()),
+                    other => {
+                        HitOutcome::from_name(other)
+                            .map(|outcome| if outcome == self.outcome { 1.0 } else { 0.0 })
+                    }
+                };
+            }
+        };
+        match first {
+            "target" => self.target.get_attribute(second),
+            "source" => self.source.get_attribute(second),
+            _ => None,
+        }
+    }
+    fn set_attribute(&mut self, var: &str, value: f64) -> Result<Option<f64>,()> {
+        let mut splitn = var.splitn(2, '.');
+        let first = match splitn.next() {
+            Some(first) => first,
+            None => return Err(()),
+        };
+        let second = match splitn.next() {
+            Some(s) => s,
+            None => {
+                // Outcome of the attack, e.g. `$critical = 1`
+                return match HitOutcome::from_name(first) {
+                    Some(outcome) => {
+                        if value != 0.0 {
+                            self.outcome = outcome;
+                        }
+                        Ok(None)
+                    }
+                    None => Err(()),
+                };
+            }
+        };
+        let side = match first {
+            "target" => Side::Target,
+            "source" => Side::Source,
+            _ => return Err(()),
+        };
+        // `damage` is physical damage, other types are set with e.g. `damage.fire`
+        let damage_type = if second == "damage" {
+            Some(DamageType::Physical)
+        } else if second.starts_with("damage.") {
+            match DamageType::from_name(&second["damage.".len()..]) {
+                Some(damage_type) => Some(damage_type),
+                None => return Err(()),
+            }
+        } else {
+            None
+        };
+        if let Some(damage_type) = damage_type {
+            self.damages.push((side, damage_type, value));
+            return Ok(None);
+        }
+        let away = self.away_from_source();
+        match first {
+            "target" => {
+                set_attribute(self.target, away, second, value)
+            }
+            "source" => {
+                set_attribute(self.source, -away, second, value)
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+fn damage(
+    entity: &mut Entity,
+    source: Id<Entity>,     // Can potentially be the same as entity.id
+    damage_type: DamageType,
+    outcome: HitOutcome,
+    value: f64,
+    notifications: &mut Vec<Notification>,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    ) {
+    if entity.pv != 0 {
+        let amount = damage_type.mitigate(value, &entity.stats.resistances);
+        notifications.push(Notification::Damage {
+            source: source.as_u64(),
+            victim: entity.id.as_u64(),
+            amount: amount as u64,
+            outcome: outcome,
+            damage_type: damage_type,
+        });
+        let new_pv = entity.pv as f64 - amount;
+        if new_pv < 0.0 {
+            // Death of entity
+            entity.pv = 0;
+            let _ = entity.transition(Event::Die);
+            dead_entities_id.push(entity.id);
+        } else {
+            entity.pv = new_pv as u64;
+        }
+    } else {
+        warn!("Trying to damage a dead entity {}", entity.id);
+    }
+}
+
+fn set_attribute(
+    entity: &mut Entity,
+    away: Vector2<f32>,     // Unit vector pointing away from the other entity
+    var: &str,
+    value: f64,
+    ) -> Result<Option<f64>,()> {
+    match var {
+        "heal" => {
+            // Dead entities cannot be healed, nor can healing go over the maximum
+            if entity.pv != 0 && entity.pv < entity.stats.max_pv && value > 0.0 {
+                entity.pv = entity.pv.saturating_add(value as u64).min(entity.stats.max_pv);
+            }
+            Ok(None)
+        }
+        "knockback" => {
+            entity.knockback = entity.knockback + away * value as f32;
+            Ok(None)
+        }
+        "experience" => {
+            // Monsters do not gain experience
+            if let EntityType::Player(ref mut player) = entity.e_type {
+                if value > 0.0 {
+                    player.experience += value as u64;
+                }
+            }
+            Ok(None)
+        }
+        other => {
+            // Status effects, the value is the duration in seconds
+            match StatusKind::from_name(other) {
+                Some(kind) => {
+                    entity.status.apply(kind, value as f32);
+                    Ok(None)
+                }
+                None => Err(()),
+            }
+        }
+    }
+}
+
+impl <'a, 'b, 'c, 'd, 'e> AaribaIntegration<'a, 'b, 'c, 'd, 'e> {
+    fn new(
+        source: &'a mut Entity,
+        target: &'b mut Entity,
+        notifications: &'c mut Vec<Notification>,
+        dead_entities_id: &'d mut Vec<Id<Entity>>,
+        rng: &'e mut InstanceRng,
+        ) -> AaribaIntegration<'a, 'b, 'c, 'd, 'e> {
+        AaribaIntegration {
+            source: source,
+            target: target,
+            notifications: notifications,
+            dead_entities_id: dead_entities_id,
+            rng: RefCell::new(rng),
+            outcome: HitOutcome::Hit,
+            damages: Vec::new(),
+        }
+    }
+
+    // Applies the damage declared by the script, or notifies that the attack did not land
+    fn apply_damages(&mut self) {
+        if !self.outcome.lands() {
+            self.notifications.push(Notification::Damage {
+                source: self.source.id.as_u64(),
+                victim: self.target.id.as_u64(),
+                amount: 0,
+                outcome: self.outcome,
+                damage_type: DamageType::Physical,
+            });
+            self.damages.clear();
+            return;
+        }
+        let source_id = self.source.id;
+        for (side, damage_type, value) in self.damages.drain(..) {
+            let entity = match side {
+                Side::Source => &mut *self.source,
+                Side::Target => &mut *self.target,
+            };
+            damage(entity,
+                   source_id,
+                   damage_type,
+                   self.outcome,
+                   value,
+                   self.notifications,
+                   self.dead_entities_id);
+        }
+    }
+
+    // Unit vector from the source to the target
+    // If they are at the same position, the orientation of the source is used instead
+    fn away_from_source(&self) -> Vector2<f32> {
+        super::direction_to(self.source.position, self.target.position, self.source.orientation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use aariba;
+    use aariba::rules::RulesEvaluator;
+
+    use entity::{Entity,DamageType,HitOutcome};
+    use messages::Notification;
+    use instance;
+    use tests::fixtures::monster;
+    use super::apply_effect;
+
+    // Returns the Damage notifications of each evaluation
+    fn evaluate(script: &RulesEvaluator, target: &mut Entity, seed: u64, times: usize) -> Vec<(u64, HitOutcome, DamageType)> {
+        let mut source = monster(0.0, 0.0);
+        let mut rng = instance::seeded_rng(seed);
+        let mut notifications = Vec::new();
+        for _ in 0..times {
+            apply_effect(script, &mut source, target, &mut notifications, &mut Vec::new(), &mut rng);
+        }
+        notifications.into_iter().filter_map(|n| match n {
+            Notification::Damage { amount, outcome, damage_type, .. } => Some((amount, outcome, damage_type)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn resistances() {
+        let script = aariba::parse_rule("$target.damage.fire = 40;\n$target.damage = 40;").unwrap();
+        let mut target = monster(1.0, 0.0);
+        target.stats.resistances.fire = 100;
+        target.stats.resistances.physical = 50;
+        let pv = target.pv;
+        let damages = evaluate(&script, &mut target, 42, 1);
+        assert_eq!(damages, vec![
+            (0, HitOutcome::Hit, DamageType::Fire),
+            (20, HitOutcome::Hit, DamageType::Physical),
+        ]);
+        assert_eq!(target.pv, pv - 20);
+    }
+
+    #[test]
+    fn outcomes_depend_on_the_seed() {
+        let script = aariba::parse_rule("$critical = max($random - 0.7, 0);\n\
+                                         $dodge = max(0.3 - $random, 0);\n\
+                                         $target.damage = 1;").unwrap();
+        let outcomes = |seed| {
+            let mut target = monster(1.0, 0.0);
+            evaluate(&script, &mut target, seed, 50).into_iter().map(|(_, outcome, _)| outcome).collect::<Vec<_>>()
+        };
+        let first = outcomes(7);
+        assert!(first.contains(&HitOutcome::Critical));
+        assert!(first.contains(&HitOutcome::Dodge));
+        assert_eq!(first, outcomes(7));
+        assert!(first != outcomes(8));
+    }
+}
@@ -3,11 +3,15 @@
 
 use std::collections::HashMap;
 
+use nalgebra::{Point2,Vector2};
+use rand::Rng;
+
 use entity::{
     Entity,
     Order,
     EntityStore,
     OthersAccessor,
+    Projectiles,
 };
 use messages::Notification;
 use id::Id;
@@ -15,17 +19,41 @@ use id::Id;
 use lycan_serialize::Direction;
 use instance::{
     TickEvent,
+    InstanceRng,
 };
 use scripts::AaribaScripts;
+use instance::geometry::MapGeometry;
 
+mod aoe;
 mod attacks;
+mod effects;
 mod movement;
+mod projectiles;
+mod skills;
+
+// Something an entity or a projectile does during a tick, that can affect the others
+#[derive(Debug,Clone,Copy)]
+enum Action {
+    Move(Id<Entity>),
+    Combat(Id<Entity>, CombatAction),
+    Projectile(u64),
+}
+
+// Actions resolved with the other entities at hand
+#[derive(Debug,Clone,Copy)]
+enum CombatAction {
+    Attack,
+    Cast,
+}
 
 /// Triggers all temporal effects
 pub fn update(
     entities: &mut EntityStore,
+    projectiles: &mut Projectiles,
+    geometry: &MapGeometry,
     notifications: &mut Vec<Notification>,
     scripts: &AaribaScripts,
+    rng: &mut InstanceRng,
     tick_duration: f32,
     ) -> Vec<TickEvent> {
     // During a tick, every event that can affect an entity (an entity attacking, a spell cast,
@@ -38,12 +66,149 @@ pub fn update(
     // when happening during the same tick
 
     let mut tick_events = Vec::new();
-    movement::resolve_movements(entities, notifications, tick_duration);
-    attacks::resolve_attacks(entities, notifications, scripts, &mut tick_events, tick_duration);
+    update_timers(entities, tick_duration);
+
+    let mut actions = collect_actions(entities, projectiles);
+    rng.shuffle(&mut actions);
+
+    // Indicates entities that die during that tick
+    // As soon as the entity dies, it should *stop interracting with the world*
+    let mut dead_entities_id = vec![];
+    for action in actions {
+        match action {
+            Action::Projectile(id) => {
+                projectiles::resolve_projectile(id,
+                                                entities,
+                                                projectiles,
+                                                geometry,
+                                                notifications,
+                                                scripts,
+                                                &mut dead_entities_id,
+                                                rng,
+                                                tick_duration);
+            }
+            Action::Move(id) => {
+                if dead_entities_id.contains(&id) {
+                    continue;
+                }
+                // The spatial index follows the movement
+                entities.move_entity(id, |entity| {
+                    movement::resolve_movement(entity, notifications, tick_duration);
+                });
+            }
+            Action::Combat(id, combat) => {
+                if dead_entities_id.contains(&id) {
+                    continue;
+                }
+                let (entity, mut others) = match entities.get_mut_wrapper(id) {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+                match combat {
+                    CombatAction::Attack => {
+                        attacks::resolve_attack(entity,
+                                                &mut others,
+                                                geometry,
+                                                notifications,
+                                                scripts,
+                                                &mut dead_entities_id,
+                                                rng);
+                    }
+                    CombatAction::Cast => {
+                        skills::resolve_cast(entity,
+                                             &mut others,
+                                             projectiles,
+                                             geometry,
+                                             notifications,
+                                             scripts,
+                                             &mut dead_entities_id,
+                                             rng,
+                                             tick_duration);
+                    }
+                }
+            }
+        }
+    }
+
+    remove_dead_entities(entities, dead_entities_id, &mut tick_events);
+    update_states(entities, notifications);
     generate_position_updates(entities, notifications);
     tick_events
 }
 
+fn collect_actions(entities: &EntityStore, projectiles: &Projectiles) -> Vec<Action> {
+    let mut actions = Vec::new();
+    for entity in entities.iter() {
+        actions.push(Action::Move(entity.id));
+        if attacks::is_attacking(entity) {
+            actions.push(Action::Combat(entity.id, CombatAction::Attack));
+        }
+        if entity.is_casting() {
+            actions.push(Action::Combat(entity.id, CombatAction::Cast));
+        }
+    }
+    for projectile in projectiles.iter() {
+        actions.push(Action::Projectile(projectile.id));
+    }
+    actions
+}
+
+// Removes the entities killed during this tick
+fn remove_dead_entities(
+    entities: &mut EntityStore,
+    dead_entities_id: Vec<Id<Entity>>,
+    events: &mut Vec<TickEvent>,
+    ) {
+    for dead_id in dead_entities_id {
+        match entities.remove(dead_id) {
+            Some(dead_entity) => {
+                events.push(TickEvent::EntityDeath(dead_entity));
+            }
+            None => {
+                error!("Could not find dead entity {} in the store, but it was scheduled for removal",
+                       dead_id);
+            }
+        }
+    }
+}
+
+fn unit_vector(direction: Direction) -> Vector2<f32> {
+    match direction {
+        Direction::North => Vector2::new(0.0, 1.0),
+        Direction::South => Vector2::new(0.0, -1.0),
+        Direction::East  => Vector2::new(1.0, 0.0),
+        Direction::West  => Vector2::new(-1.0, 0.0),
+    }
+}
+
+// Unit vector from `from` to `to`, or in the given direction if they are at the same position
+fn direction_to(from: Point2<f32>, to: Point2<f32>, fallback: Direction) -> Vector2<f32> {
+    let diff = to - from;
+    let norm = (diff.x * diff.x + diff.y * diff.y).sqrt();
+    if norm > 0.0 {
+        diff / norm
+    } else {
+        unit_vector(fallback)
+    }
+}
+
+// Timers are not actions, they are updated at the beginning of the tick
+fn update_timers(entities: &mut EntityStore, tick_duration: f32) {
+    for entity in entities.iter_mut() {
+        entity.status.update(tick_duration);
+        attacks::update_reloading(entity, tick_duration);
+        skills::update_cooldowns(entity, tick_duration);
+    }
+}
+
+// The state machines follow the status changes of the tick, so that the next orders are
+// validated against them
+fn update_states(entities: &mut EntityStore, notifications: &mut Vec<Notification>) {
+    for entity in entities.iter_mut() {
+        notifications.extend(entity.follow_status());
+    }
+}
+
 fn generate_position_updates(
     entities: &EntityStore,
     notifications: &mut Vec<Notification>,
@@ -59,3 +224,50 @@ fn generate_position_updates(
     }
 }
 
+#[cfg(test)]
+mod test {
+    use lycan_serialize::Direction;
+
+    use entity::{Order,Projectiles};
+    use messages::Notification;
+    use instance;
+    use instance::geometry::MapGeometry;
+    use scripts;
+    use utils::ConfigurationSource;
+    use tests::fixtures::{monster,store};
+
+    // Two entities attack each other during the same tick, true if the left one hits first
+    fn left_hits_first(seed: u64) -> bool {
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts");
+        let (scripts, _) = scripts::load_scripts(&ConfigurationSource::new(directory)).unwrap();
+        let mut left = monster(0.0, 0.0);
+        let mut right = monster(0.75, 0.0);
+        left.orientation = Direction::East;
+        right.orientation = Direction::West;
+        assert!(left.apply(Order::Attack).is_ok());
+        assert!(right.apply(Order::Attack).is_ok());
+        let left_id = left.get_id().as_u64();
+        let mut entities = store(vec![left, right]);
+        let mut notifications = Vec::new();
+        super::update(&mut entities,
+                      &mut Projectiles::new(),
+                      &MapGeometry::empty(),
+                      &mut notifications,
+                      &scripts,
+                      &mut instance::seeded_rng(seed),
+                      0.05);
+        let first = notifications.iter().filter_map(|n| match *n {
+            Notification::Damage { source, .. } => Some(source),
+            _ => None,
+        }).next().expect("nobody was hit");
+        first == left_id
+    }
+
+    #[test]
+    fn resolution_order_depends_on_the_seed() {
+        let results: Vec<_> = (0..20).map(left_hits_first).collect();
+        assert_eq!(results, (0..20).map(left_hits_first).collect::<Vec<_>>());
+        assert!(results.contains(&true));
+        assert!(results.contains(&false));
+    }
+}
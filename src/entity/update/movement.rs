@@ -10,34 +10,20 @@ use entity::{
     EntityStore,
 };
 
-pub fn resolve_movements(
-    entities: &mut EntityStore,
-    notifications: &mut Vec<Notification>,
-    tick_duration: f32,
-    ) {
-    for entity in entities.iter_mut() {
-        resolve_collisions(entity, notifications, tick_duration)
-    }
-}
-
-fn resolve_collisions(
+pub fn resolve_movement(
     entity: &mut Entity,
     _notifications: &mut Vec<Notification>,
     tick_duration: f32,
     ) {
     // Assume no collisions at the moment ...
-    let unitary_speed = if entity.walking {
-        match entity.orientation {
-            Direction::North => Vector2::new(0.0, 1.0),
-            Direction::South => Vector2::new(0.0, -1.0),
-            Direction::East  => Vector2::new(1.0, 0.0),
-            Direction::West  => Vector2::new(-1.0, 0.0),
-        }
+    let unitary_speed = if entity.state.is_walking() && entity.status.can_move() {
+        super::unit_vector(entity.orientation)
     } else {
         Vector2::new(0.0, 0.0)
     };
     let speed = unitary_speed * entity.stats.speed;
-    let new_position = entity.position + speed * tick_duration;
+    let new_position = entity.position + speed * tick_duration + entity.knockback;
     entity.position = new_position;
+    entity.knockback = Vector2::new(0.0, 0.0);
     entity.speed = speed;
 }
@@ -0,0 +1,149 @@
+// Movement and collisions of the projectiles
+//
+// A projectile can cross a whole entity during a tick, so the collisions are checked along
+// the segment it travels, like the obstacles are
+use nalgebra::{Point2,FloatPoint};
+
+use id::Id;
+use entity::{
+    Entity,
+    EntityStore,
+    Projectile,
+    Projectiles,
+};
+use messages::Notification;
+use scripts::AaribaScripts;
+use instance::InstanceRng;
+use instance::geometry::MapGeometry;
+
+use super::aoe;
+
+/// Moves the projectile, and applies its effect if it hits an entity
+pub fn resolve_projectile(
+    id: u64,
+    entities: &mut EntityStore,
+    projectiles: &mut Projectiles,
+    geometry: &MapGeometry,
+    notifications: &mut Vec<Notification>,
+    scripts: &AaribaScripts,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    tick_duration: f32,
+    ) {
+    let alive = match projectiles.get_mut(id) {
+        Some(projectile) => {
+            advance(projectile,
+                    entities,
+                    geometry,
+                    notifications,
+                    scripts,
+                    dead_entities_id,
+                    rng,
+                    tick_duration)
+        }
+        None => return,
+    };
+    if !alive {
+        projectiles.despawn(id, notifications);
+    }
+}
+
+// Returns false if the projectile must disappear
+fn advance(
+    projectile: &mut Projectile,
+    entities: &mut EntityStore,
+    geometry: &MapGeometry,
+    notifications: &mut Vec<Notification>,
+    scripts: &AaribaScripts,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    tick_duration: f32,
+    ) -> bool {
+    projectile.lifetime -= tick_duration;
+    if projectile.lifetime <= 0.0 {
+        return false;
+    }
+    let from = projectile.position;
+    let to = from + projectile.velocity * tick_duration;
+    // An entity can be hit before the obstacle that stops the projectile
+    if resolve_hit(projectile, from, to, entities, geometry, notifications, scripts, dead_entities_id, rng) {
+        return false;
+    }
+    if !geometry.has_line_of_sight(from, to) {
+        return false;
+    }
+    projectile.position = to;
+    true
+}
+
+// Returns true if the projectile hit an entity on its way from `from` to `to`
+fn resolve_hit(
+    projectile: &Projectile,
+    from: Point2<f32>,
+    to: Point2<f32>,
+    entities: &mut EntityStore,
+    geometry: &MapGeometry,
+    notifications: &mut Vec<Notification>,
+    scripts: &AaribaScripts,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    ) -> bool {
+    let (owner, mut others) = match entities.get_mut_wrapper(projectile.owner) {
+        Some(owner) => owner,
+        None => {
+            // The owner has left or died, the projectile is harmless
+            return false;
+        }
+    };
+    // All the entities the hitbox can touch are around the middle of the segment
+    let middle = from + (to - from) / 2.0;
+    let reach = from.distance(&to) / 2.0 + projectile.hitbox.bounding_radius() + others.hitbox_margin();
+    let candidates = others.in_range(middle, reach, |e| !dead_entities_id.contains(&e.id));
+    // The first entity on the way, or the first of the store if several are touched at once
+    let mut first: Option<(Id<Entity>, f32)> = None;
+    for id in candidates {
+        let moment = match others.get(id) {
+            Some(e) => projectile.hitbox.sweep(from, to, &e.hitbox, e.position),
+            None => None,
+        };
+        if let Some(moment) = moment {
+            let earlier = match first {
+                Some((_, first_moment)) => moment < first_moment,
+                None => true,
+            };
+            if earlier {
+                first = Some((id, moment));
+            }
+        }
+    }
+    let (hit, moment) = match first {
+        Some(first) => first,
+        None => return false,
+    };
+    let impact = from + (to - from) * moment;
+    if !geometry.has_line_of_sight(from, impact) {
+        return false;
+    }
+    match scripts.get(&projectile.effect) {
+        Some(effect) => {
+            let direction = super::direction_to(from, to, owner.orientation);
+            aoe::apply_skill_area(effect,
+                                  projectile.area,
+                                  impact,
+                                  impact,
+                                  direction,
+                                  Some(hit.into()),
+                                  owner,
+                                  &mut others,
+                                  geometry,
+                                  notifications,
+                                  dead_entities_id,
+                                  rng);
+        }
+        None => {
+            // Can happen if the scripts were reloaded
+            warn!("Could not find effect {} of projectile {}", projectile.effect, projectile.id);
+        }
+    }
+    true
+}
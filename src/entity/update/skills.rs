@@ -0,0 +1,238 @@
+// Casting of skills
+//
+// A cast order is validated during the first tick (unknown skill, cooldown, energy, range),
+// then the entity casts for the duration defined by the skill. The cast is interrupted if the
+// entity is silenced, and the state machine of the entity interrupts it when it moves or is
+// stunned. When the cast completes, the effect script of the skill
+// is evaluated on every entity in its area, or a projectile carrying the effect is launched.
+use std::mem;
+
+use nalgebra::{Point2,FloatPoint};
+
+use id::{Id,WeakId};
+use instance::{
+    TickEvent,
+};
+use entity::{
+    Entity,
+    EntityStore,
+    OthersAccessor,
+    StatusKind,
+    CastOrder,
+    CastTarget,
+    Projectiles,
+    MAX_ENERGY,
+    ENERGY_REGENERATION,
+};
+use entity::cast::CastState;
+use entity::state::State;
+use data::{Skill,SkillArea};
+use messages::Notification;
+use scripts::AaribaScripts;
+use instance::InstanceRng;
+use instance::geometry::MapGeometry;
+
+use super::aoe;
+
+pub fn update_cooldowns(entity: &mut Entity, tick_duration: f32) {
+    entity.cooldowns.update(tick_duration);
+    entity.energy = (entity.energy + ENERGY_REGENERATION * tick_duration).min(MAX_ENERGY);
+}
+
+/// Starts, continues, interrupts or completes the cast of the entity
+pub fn resolve_cast(
+    entity: &mut Entity,
+    others: &mut OthersAccessor,
+    projectiles: &mut Projectiles,
+    geometry: &MapGeometry,
+    notifications: &mut Vec<Notification>,
+    scripts: &AaribaScripts,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    tick_duration: f32,
+    ) {
+    // The entity is left idle during the resolution, and casting again if the cast goes on
+    let cast = match mem::replace(&mut entity.state, State::Idle) {
+        State::Casting(CastState::Requested(order)) => {
+            start_cast(entity, others, order, scripts, notifications)
+        }
+        State::Casting(cast) => cast,
+        other => {
+            entity.state = other;
+            return;
+        }
+    };
+    let cast = match cast {
+        CastState::Casting { order, remaining } => {
+            if is_interrupted(entity) {
+                notifications.push(Notification::CastInterrupted {
+                    entity: entity.id.as_u64(),
+                    skill: order.skill,
+                });
+                CastState::Idle
+            } else if remaining > 0.0 {
+                CastState::Casting { order: order, remaining: remaining - tick_duration }
+            } else {
+                let completed = complete_cast(entity,
+                                              others,
+                                              geometry,
+                                              projectiles,
+                                              &order,
+                                              scripts,
+                                              notifications,
+                                              dead_entities_id,
+                                              rng);
+                if !completed {
+                    notifications.push(Notification::CastInterrupted {
+                        entity: entity.id.as_u64(),
+                        skill: order.skill,
+                    });
+                }
+                CastState::Idle
+            }
+        }
+        other => other,
+    };
+    if !cast.is_idle() {
+        entity.state = State::Casting(cast);
+    }
+}
+
+fn is_interrupted(entity: &Entity) -> bool {
+    !entity.status.can_act() || entity.status.has(StatusKind::Silenced)
+}
+
+fn find_target<'a>(others: &'a OthersAccessor, target: WeakId<Entity>) -> Option<&'a Entity> {
+    others.iter().find(|e| WeakId::from(e.get_id()) == target)
+}
+
+// Checks that the skill can be cast, and pays its cost
+fn start_cast(
+    entity: &mut Entity,
+    others: &OthersAccessor,
+    order: CastOrder,
+    scripts: &AaribaScripts,
+    notifications: &mut Vec<Notification>,
+    ) -> CastState {
+    let skill = match scripts.get_skill(&order.skill) {
+        Some(skill) => skill,
+        None => {
+            warn!("Entity {} tried to cast unknown skill {}", entity.id, order.skill);
+            return CastState::Idle;
+        }
+    };
+    if !entity.cooldowns.is_ready(&skill.name) {
+        debug!("Skill {} of entity {} is in cooldown", skill.name, entity.id);
+        return CastState::Idle;
+    }
+    if entity.energy < skill.cost as f32 {
+        debug!("Not enough energy for entity {} to cast {}", entity.id, skill.name);
+        return CastState::Idle;
+    }
+    match order.target {
+        CastTarget::Direction(direction) => entity.orientation = direction,
+        CastTarget::Entity(target) => {
+            let in_range = find_target(others, target)
+                .map(|target| in_range(entity.position, target.position, skill))
+                .unwrap_or(false);
+            if !in_range {
+                debug!("Target {} out of range for entity {} casting {}", target, entity.id, skill.name);
+                return CastState::Idle;
+            }
+        }
+    }
+
+    entity.energy -= skill.cost as f32;
+    notifications.push(Notification::CastStarted {
+        entity: entity.id.as_u64(),
+        skill: skill.name.clone(),
+        duration: skill.cast_time,
+    });
+    CastState::Casting {
+        order: order,
+        remaining: skill.cast_time,
+    }
+}
+
+fn in_range(caster: Point2<f32>, target: Point2<f32>, skill: &Skill) -> bool {
+    caster.distance_squared(&target) <= skill.range * skill.range
+}
+
+// Evaluates the effect of the skill on all the entities in its area, or launches its projectile
+// Returns false if the target could not be reached
+fn complete_cast(
+    entity: &mut Entity,
+    others: &mut OthersAccessor,
+    geometry: &MapGeometry,
+    projectiles: &mut Projectiles,
+    order: &CastOrder,
+    scripts: &AaribaScripts,
+    notifications: &mut Vec<Notification>,
+    dead_entities_id: &mut Vec<Id<Entity>>,
+    rng: &mut InstanceRng,
+    ) -> bool {
+    // The skill and its effect can have disappeared if the scripts were reloaded
+    let skill = match scripts.get_skill(&order.skill) {
+        Some(skill) => skill,
+        None => return false,
+    };
+    let effect = match scripts.get(&skill.effect) {
+        Some(effect) => effect,
+        None => return false,
+    };
+    let center = match order.target {
+        CastTarget::Direction(direction) => {
+            entity.position + super::unit_vector(direction) * skill.range
+        }
+        CastTarget::Entity(target) => {
+            match find_target(others, target) {
+                Some(target) if in_range(entity.position, target.position, skill) => target.position,
+                _ => return false,
+            }
+        }
+    };
+
+    let direction = super::direction_to(entity.position, center, entity.orientation);
+
+    // The effect is applied when the projectile hits something
+    if let Some(ref projectile) = skill.projectile {
+        entity.cooldowns.start(&skill.name, skill.cooldown);
+        let lifetime = projectile.lifetime.unwrap_or(skill.range / projectile.speed);
+        projectiles.spawn(entity.id,
+                          entity.position,
+                          direction * projectile.speed,
+                          lifetime,
+                          projectile.size,
+                          skill.effect.clone(),
+                          skill.area,
+                          notifications);
+        return true;
+    }
+
+    if !geometry.has_line_of_sight(entity.position, center) {
+        return false;
+    }
+    let target = match (skill.area, order.target) {
+        (SkillArea::Target, CastTarget::Direction(_)) => {
+            warn!("Skill {} needs a target entity", skill.name);
+            return false;
+        }
+        (_, CastTarget::Entity(target)) => Some(target),
+        (_, CastTarget::Direction(_)) => None,
+    };
+    entity.cooldowns.start(&skill.name, skill.cooldown);
+    let origin = entity.position;
+    aoe::apply_skill_area(effect,
+                          skill.area,
+                          origin,
+                          center,
+                          direction,
+                          target,
+                          entity,
+                          others,
+                          geometry,
+                          notifications,
+                          dead_entities_id,
+                          rng);
+    true
+}
@@ -21,10 +21,11 @@ use lycan_serialize::AuthenticationToken;
 use id::{Id,WeakId};
 use messages::Request as LycanRequest;
 use messages::Command;
-use data::{ConnectCharacterParam,Map,GetInstances,GetMaps};
+use data::{ConnectCharacterParam,Map,GetInstances,GetMaps,AiDebug};
 use entity::Entity;
 use instance::management::*;
 use game::Game;
+use scripts;
 
 // XXX FIXME TODO: Remove
 // mio channels were sync, std lib channels are not
@@ -54,7 +55,13 @@ impl <T> Clone for MutexSender<T> {
 // - Set correct headers in all responses
 // - Check if correct heahers are set (e.g. Content-Type)
 
-pub fn start_management_api(sender: Sender<LycanRequest>) {
+pub const MANAGEMENT_ADDRESS: &'static str = "127.0.0.1:9001";
+
+/// Starts the management API in its own thread
+///
+/// Returns once the server is listening, or if it failed to do so
+pub fn start_management_api(addr: &'static str, sender: Sender<LycanRequest>) -> Result<(),HttpError> {
+    let (tx, rx) = mpsc::channel();
     thread::spawn(move || {
         let sender = MutexSender::new(sender);
         let router = create_router(sender);
@@ -72,8 +79,19 @@ pub fn start_management_api(sender: Sender<LycanRequest>) {
         chain.link_after(error_router);
 
         let iron = Iron::new(chain);
-        iron.http("127.0.0.1:9001").unwrap();
+        match iron.http(addr) {
+            Ok(listening) => {
+                let _ = tx.send(Ok(()));
+                // Dropping the guard blocks until the server stops
+                drop(listening);
+            }
+            Err(e) => {
+                let _ = tx.send(Err(e));
+            }
+        }
     });
+    // If the thread panicked before sending anything, there is nothing we can report
+    rx.recv().unwrap_or(Ok(()))
 }
 
 macro_rules! itry_map {
@@ -178,6 +196,44 @@ fn create_router(sender: MutexSender<LycanRequest>) -> Router {
         }),
         "entities");
 
+    let clone = sender.clone();
+    server.get(
+        "/instances/:id/ai_statistics",
+        correct_bounds(move |request| {
+            // id is part of the route, the unwrap should never fail
+            let params = request.extensions.get::<Router>().unwrap();
+            let id = &params["id"];
+            let parsed = itry_map!(id.parse::<u64>(), |e| (Status::BadRequest, format!("ERROR: invalid id {}: {}", id, e)));
+            let statistics = itry_map!(define_request_instance!(clone, parsed, |instance| {
+                instance.get_ai_statistics()
+            }),
+            |_e| (Status::BadRequest, format!("ERROR: Non existent instance id {}", parsed)));
+            Ok(Response::with((Status::Ok,JsonWriter(statistics))))
+        }),
+        "ai_statistics");
+
+    let clone = sender.clone();
+    server.post(
+        "/instances/:id/tick",
+        correct_bounds(move |request| {
+            // id is part of the route, the unwrap should never fail
+            let params = request.extensions.get::<Router>().unwrap();
+            let id = &params["id"];
+            let parsed = itry_map!(id.parse::<u64>(), |e| (Status::BadRequest, format!("ERROR: invalid id {}: {}", id, e)));
+            let result = itry_map!(define_request_instance!(clone, parsed, |instance| {
+                instance.manual_tick()
+            }),
+            |_e| (Status::BadRequest, format!("ERROR: Non existent instance id {}", parsed)));
+            match result {
+                Ok(tick) => Ok(Response::with((Status::Ok,JsonWriter(tick)))),
+                Err(ManualTickError::Periodic) => {
+                    Ok(Response::with((Status::BadRequest,
+                                       format!("ERROR: The ticks of instance {} are not manual", parsed))))
+                }
+            }
+        }),
+        "tick");
+
     let clone = sender.clone();
     server.get(
         "/players",
@@ -228,6 +284,26 @@ fn create_router(sender: MutexSender<LycanRequest>) -> Router {
         }),
         "shutdown");
 
+    let clone = sender.clone();
+    server.post(
+        "/reload_scripts",
+        correct_bounds(move |_request| {
+            let configuration = define_request!(clone, |game| {
+                game.configuration.clone()
+            });
+            // Fetch and parse outside of the Game, so it is not blocked in the meantime
+            let (scripts, trees) = itry_map!(scripts::load_scripts(&configuration), |e|
+                                             (Status::BadRequest, format!("ERROR: {}", e)));
+            let result = define_request!(clone, |game| {
+                game.reload_scripts(scripts, trees)
+            });
+            match result {
+                Ok(()) => Ok(Response::with((Status::Ok, "OK"))),
+                Err(e) => Ok(Response::with((Status::BadRequest, format!("ERROR: {}", e)))),
+            }
+        }),
+        "reload_scripts");
+
     let clone = sender.clone();
     server.post(
         "/connect_character",
@@ -267,6 +343,40 @@ fn create_router(sender: MutexSender<LycanRequest>) -> Router {
             }
         })
     }
+    // Isolated in a function for easier error handling
+    fn entity_ai(sender: &MutexSender<LycanRequest>, request: &mut Request) -> Result<AiDebug,String> {
+        let params = request.extensions.get::<Router>().unwrap();
+        // id is part of the route, the unwrap should never fail
+        let instance_id = {
+            let id = &params["instance_id"];
+            try!(id.parse::<u64>().map_err(|e| format!("ERROR: invalid instance id {}: {}", id, e)))
+        };
+        let entity_id: WeakId<Entity> = {
+            let id = &params["entity_id"];
+            let id_u64 = try!(id.parse::<u64>().map_err(|e| format!("ERROR: invalid entity id {}: {}", id, e)));
+            WeakId::new(id_u64)
+        };
+        let result = try!(define_request_instance!(sender, instance_id, |instance| {
+            instance.get_ai_debug(entity_id)
+        }).map_err(|_e| format!("ERROR: Non existent instance id {}", instance_id)));
+        result.map_err(|e| {
+            match e {
+                AiDebugError::NotFound => format!("ERROR: Entity {} not found in instance {}", entity_id, instance_id),
+                AiDebugError::NotAi => format!("ERROR: Entity {} is not controlled by an AI", entity_id),
+            }
+        })
+    }
+    let clone = sender.clone();
+    server.get(
+        "/instances/:instance_id/entities/:entity_id/ai",
+        correct_bounds(move |request| {
+            match entity_ai(&clone, request) {
+                Ok(debug) => Ok(Response::with((Status::Ok,JsonWriter(debug)))),
+                Err(s) => Ok(Response::with((Status::BadRequest, s))),
+            }
+        }),
+        "entity_ai");
+
     let clone = sender.clone();
     server.delete(
         "/instances/:instance_id/entities/:entity_id",
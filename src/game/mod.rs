@@ -4,11 +4,14 @@ use std::thread;
 use std::io;
 use std::boxed::FnBox;
 use std::sync::mpsc::{self,Receiver,Sender};
+use std::time::Duration;
+use std::fmt;
+use std::path::PathBuf;
 
 use lycan_serialize::AuthenticationToken;
 
-use utils;
-use instance::{InstanceRef,Instance};
+use utils::{self,ConfigurationSource};
+use instance::{InstanceRef,Instance,InstanceParameters};
 use actor::{NetworkActor,ActorId};
 use id::{Id,HasId,WeakId};
 use data::{Player,Map,EntityManagement,EntityType};
@@ -16,7 +19,9 @@ use data::UNIQUE_MAP;
 use entity::{Entity};
 use messages::{Command,Request,Notification};
 use network;
-use scripts::{AaribaScripts,BehaviourTrees};
+use scripts::{self,AaribaScripts,BehaviourTrees};
+
+use iron::error::HttpError;
 
 use self::resource_manager::{Error,ResourceManager};
 use self::authentication::AuthenticationManager;
@@ -28,11 +33,43 @@ mod management;
 
 const RESOURCE_MANAGER_THREADS: usize = 2;
 
+/// Reason why the Game could not be started
+#[derive(Debug)]
+pub enum StartError {
+    Scripts(scripts::Error),
+    // Failed to listen on the port for game clients
+    GameBind(SocketAddr, io::Error),
+    // Failed to listen on the port of the management API
+    ManagementBind(String, HttpError),
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            StartError::Scripts(ref e) => write!(f, "Could not load the scripts: {}", e),
+            StartError::GameBind(ref addr, ref e) => {
+                write!(f, "Could not listen for clients on {}: {}", addr, e)
+            }
+            StartError::ManagementBind(ref addr, ref e) => {
+                write!(f, "Could not start the management API on {}: {}", addr, e)
+            }
+        }
+    }
+}
+
 #[derive(Debug,Clone)]
 pub struct GameParameters {
     pub port: u16,
     pub configuration_url: String,
     pub tick_duration: f32,
+    // Seed of the random generator of the instances, random if not set
+    pub seed: Option<u64>,
+    // The ticks of the instances are only calculated when requested through the management API
+    pub manual_ticks: bool,
+    // Directory in which the inputs of the instances are recorded, for them to be replayed
+    pub record_directory: Option<String>,
+    // Period in seconds at which the scripts are checked for modifications, 0 to disable
+    pub script_watch_period: f32,
 }
 
 pub struct Game {
@@ -44,13 +81,14 @@ pub struct Game {
     resource_manager: ResourceManager,
     authentication_manager: AuthenticationManager,
     sender: Sender<Request>,
-    tick_duration: f32,
+    instance_parameters: InstanceParameters,
     callbacks: Callbacks,
     shutdown: bool,
 
     // TODO: Should this be integrated with the resource manager?
     scripts: AaribaScripts,
     trees: BehaviourTrees,
+    configuration: ConfigurationSource,
 }
 
 impl Game {
@@ -58,8 +96,8 @@ impl Game {
         scripts: AaribaScripts,
         trees: BehaviourTrees,
         sender: Sender<Request>,
-        base_url: String,
-        tick_duration: f32,
+        configuration: ConfigurationSource,
+        instance_parameters: InstanceParameters,
         ) -> Game {
         Game {
             map_instances: HashMap::new(),
@@ -67,34 +105,50 @@ impl Game {
             players: HashMap::new(),
             sender: sender.clone(),
             authentication_manager: AuthenticationManager::new(),
-            resource_manager: ResourceManager::new(RESOURCE_MANAGER_THREADS, sender, base_url),
-            tick_duration: tick_duration,
+            resource_manager: ResourceManager::new(RESOURCE_MANAGER_THREADS, sender, configuration.clone()),
+            instance_parameters: instance_parameters,
             callbacks: Callbacks::new(),
             shutdown: false,
             scripts: scripts,
             trees: trees,
+            configuration: configuration,
         }
     }
 
-    pub fn spawn_game(parameters: GameParameters) -> Result<Sender<Request>,io::Error> {
-        let scripts = AaribaScripts::get_from_url(&parameters.configuration_url).unwrap();
-        let behaviour_trees = BehaviourTrees::get_from_url(&parameters.configuration_url).unwrap();
+    pub fn spawn_game(parameters: GameParameters) -> Result<Sender<Request>,StartError> {
+        let configuration = ConfigurationSource::new(&parameters.configuration_url);
+        let (scripts, behaviour_trees) = try!(scripts::load_scripts(&configuration)
+                                              .map_err(StartError::Scripts));
 
         let (sender, rx) = mpsc::channel();
 
         let ip = net::IpAddr::V4(Ipv4Addr::new(0,0,0,0));
         let addr = SocketAddr::new(ip,parameters.port);
-        network::start_server(addr, sender.clone());
+        try!(network::start_server(addr, sender.clone())
+             .map_err(|e| StartError::GameBind(addr, e)));
 
-        management::start_management_api(sender.clone());
+        let management_addr = management::MANAGEMENT_ADDRESS;
+        try!(management::start_management_api(management_addr, sender.clone())
+             .map_err(|e| StartError::ManagementBind(management_addr.to_string(), e)));
         let mut game = Game::new(
             scripts,
             behaviour_trees,
             sender.clone(),
-            parameters.configuration_url.clone(),
-            parameters.tick_duration,
+            configuration.clone(),
+            InstanceParameters {
+                tick_duration: parameters.tick_duration,
+                seed: parameters.seed,
+                manual_ticks: parameters.manual_ticks,
+                record_directory: parameters.record_directory.as_ref().map(PathBuf::from),
+            },
             );
 
+        if parameters.script_watch_period > 0.0 {
+            start_script_watcher(configuration.clone(),
+                                 parameters.script_watch_period,
+                                 sender.clone());
+        }
+
         // XXX: Hacks
         game.authentication_manager.fake_authentication_tokens();
         let _ = game.resource_manager.load_map(UNIQUE_MAP.get_id());
@@ -194,6 +248,19 @@ impl Game {
         actor: NetworkActor,
         entities: Vec<Entity>,
         ) {
+        let map_data = match self.resource_manager.get_map(map) {
+            Ok(map_data) => map_data,
+            Err(Error::Processing(job)) => {
+                self.callbacks.add(job, move |game| {
+                    game.assign_actor_to_map(map, actor, entities);
+                });
+                return;
+            }
+            Err(Error::NotFound) => {
+                error!("Trying to access nonexisting map {}", map);
+                return;
+            }
+        };
         match self.map_instances.get_mut(&map) {
             Some(instances) => {
                 // TODO: Load balancing
@@ -209,8 +276,8 @@ impl Game {
                             self.sender.clone(),
                             self.scripts.clone(),
                             self.trees.clone(),
-                            map,
-                            self.tick_duration,
+                            map_data,
+                            self.instance_parameters.clone(),
                             );
                         instance.send(Command::NewClient(actor,entities)).unwrap();
                         Some(instance)
@@ -266,11 +333,69 @@ impl Game {
         self.resource_manager.load_player(id);
     }
 
+    /// Replaces the scripts used by the Game and all running instances
+    ///
+    /// The new behaviour trees must define at least all the trees currently known, as
+    /// they may be used by running AIs
+    pub fn reload_scripts(&mut self,
+                          scripts: AaribaScripts,
+                          trees: BehaviourTrees,
+                          ) -> Result<(),String> {
+        let missing = self.trees.missing_from(&trees);
+        if !missing.is_empty() {
+            return Err(format!("Behaviour trees {:?} are missing from the new scripts", missing));
+        }
+        for instance in self.instances.values() {
+            let command = Command::ReloadScripts(scripts.clone(), trees.clone());
+            if instance.send(command).is_err() {
+                warn!("Could not send scripts to instance {}", instance.get_id());
+            }
+        }
+        self.scripts = scripts;
+        self.trees = trees;
+        debug!("Scripts reloaded");
+        Ok(())
+    }
+
+    fn has_same_scripts(&self, scripts: &AaribaScripts, trees: &BehaviourTrees) -> bool {
+        self.scripts.same_sources(scripts) && self.trees.same_sources(trees)
+    }
+
     pub fn verify_token(&mut self, id: Id<Player>, token: AuthenticationToken) -> bool {
         self.authentication_manager.verify_token(id, token)
     }
 }
 
+// Periodically fetches the scripts, and reloads them in the Game if they have changed
+fn start_script_watcher(configuration: ConfigurationSource, period: f32, sender: Sender<Request>) {
+    let period = Duration::from_millis((period * 1000.0) as u64);
+    thread::spawn(move || {
+        debug!("Watching scripts at {}", configuration);
+        loop {
+            thread::sleep(period);
+            let (scripts, trees) = match scripts::load_scripts(&configuration) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    error!("Could not reload the scripts: {}", e);
+                    continue;
+                }
+            };
+            let request = Request::new(move |game| {
+                if game.has_same_scripts(&scripts, &trees) {
+                    return;
+                }
+                if let Err(e) = game.reload_scripts(scripts, trees) {
+                    error!("Could not reload the scripts: {}", e);
+                }
+            });
+            if sender.send(request).is_err() {
+                // The Game has stopped
+                break;
+            }
+        }
+    });
+}
+
 type Callback = Box<FnBox(&mut Game) + Send>;
 
 struct Callbacks {
@@ -7,7 +7,7 @@ use std::hash::Hash;
 use threadpool::ThreadPool;
 use serde_json;
 
-use utils;
+use utils::ConfigurationSource;
 use id::{Id,HasId};
 use data::{Map,Player};
 use data::UNIQUE_MAP;
@@ -21,7 +21,7 @@ pub struct ResourceManager {
     requests: Sender<Request>,
     pool: ThreadPool,
     job: usize,
-    base_url: String,
+    configuration: ConfigurationSource,
 }
 
 struct ResourceManagerInner<T: HasId,U> {
@@ -139,33 +139,33 @@ where U: RetreiveFromId<T>,
 }
 
 impl ResourceManager {
-    pub fn new(threads: usize, requests: Sender<Request>, url: String) -> ResourceManager {
+    pub fn new(threads: usize, requests: Sender<Request>, configuration: ConfigurationSource) -> ResourceManager {
         ResourceManager {
             maps: ResourceManagerInner::new(requests.clone()),
             players: ResourceManagerInner::new(requests.clone()),
             pool: ThreadPool::new(threads),
             requests: requests,
             job: 0,
-            base_url: url,
+            configuration: configuration,
         }
     }
 
     pub fn load_map(&mut self, map: Id<Map>) {
         let job = self.job;
         self.job += 1;
-        self.maps.load(map, &self.pool, job, ());
+        self.maps.load(map, &self.pool, job, self.configuration.clone());
     }
 
     pub fn get_map(&mut self, map: Id<Map>) -> Result<Arc<Map>, Error> {
         let job = self.job;
         self.job += 1;
-        self.maps.get(map, &self.pool, job, ())
+        self.maps.get(map, &self.pool, job, self.configuration.clone())
     }
 
     pub fn load_player(&mut self, player: Id<Player>) {
         let job = self.job;
         self.job += 1;
-        self.players.load(player, &self.pool, job, self.base_url.clone());
+        self.players.load(player, &self.pool, job, self.configuration.clone());
     }
 
     pub fn retrieve_player(&mut self,
@@ -173,7 +173,7 @@ impl ResourceManager {
                           ) -> Result<Entity, Error> {
         let job = self.job;
         self.job += 1;
-        self.players.retrieve(player, &self.pool, job, self.base_url.clone())
+        self.players.retrieve(player, &self.pool, job, self.configuration.clone())
     }
 
     pub fn get_all_maps(&mut self) -> Vec<Arc<Map>> {
@@ -253,10 +253,10 @@ pub enum Error {
 }
 
 impl RetreiveFromId<Player> for Entity {
-    type Info = String;
-    fn retrieve(id: Id<Player>, mut base: String) -> Result<Entity,Error> {
-        let _ = write!(base, "/entities/{}", id);
-        if let Ok(serialized_entity) = utils::get_file_from_url(&base) {
+    type Info = ConfigurationSource;
+    fn retrieve(id: Id<Player>, configuration: ConfigurationSource) -> Result<Entity,Error> {
+        let path = format!("entities/{}", id);
+        if let Ok(serialized_entity) = configuration.get_file(&path) {
             if let Ok(entity) = serde_json::from_str::<Player>(&serialized_entity) {
                 return Ok(Entity::from(entity))
             }
@@ -266,8 +266,18 @@ impl RetreiveFromId<Player> for Entity {
 }
 
 impl RetreiveFromId for Map {
-    type Info = ();
-    fn retrieve(id: Id<Map>, _: ()) -> Result<Map,Error> {
+    type Info = ConfigurationSource;
+    fn retrieve(id: Id<Map>, configuration: ConfigurationSource) -> Result<Map,Error> {
+        let path = format!("maps/{}.json", id);
+        match configuration.get_file(&path) {
+            Ok(serialized_map) => {
+                match serde_json::from_str::<Map>(&serialized_map) {
+                    Ok(map) => return Ok(map),
+                    Err(e) => error!("Could not parse map {}: {}", id, e),
+                }
+            }
+            Err(e) => debug!("Could not fetch map {}: {}", id, e),
+        }
         if id != UNIQUE_MAP.get_id() {
             Err(Error::NotFound)
         } else {
@@ -0,0 +1,120 @@
+// Static geometry of the map an instance is running
+use std::collections::HashSet;
+
+use nalgebra::Point2;
+
+use data::Map;
+
+#[derive(Debug,Clone)]
+pub struct MapGeometry {
+    tile_size: f32,
+    blocking: HashSet<(i32,i32)>,
+}
+
+impl MapGeometry {
+    pub fn new(map: &Map) -> MapGeometry {
+        MapGeometry {
+            tile_size: map.tile_size,
+            blocking: map.obstacles.iter().map(|t| (t.x, t.y)).collect(),
+        }
+    }
+
+    /// A map without any obstacle
+    pub fn empty() -> MapGeometry {
+        MapGeometry {
+            tile_size: 1.0,
+            blocking: HashSet::new(),
+        }
+    }
+
+    pub fn tile_of(&self, position: Point2<f32>) -> (i32,i32) {
+        ((position.x / self.tile_size).floor() as i32,
+         (position.y / self.tile_size).floor() as i32)
+    }
+
+    pub fn is_blocking(&self, tile: (i32,i32)) -> bool {
+        self.blocking.contains(&tile)
+    }
+
+    pub fn is_position_blocked(&self, position: Point2<f32>) -> bool {
+        self.is_blocking(self.tile_of(position))
+    }
+
+    /// Casts a ray between the two points, and returns false if it crosses a blocking tile
+    ///
+    /// Uses the voxel traversal algorithm of Amanatides and Woo, so every tile crossed by
+    /// the segment is visited exactly once
+    pub fn has_line_of_sight(&self, from: Point2<f32>, to: Point2<f32>) -> bool {
+        if self.blocking.is_empty() {
+            return true;
+        }
+        let (mut x, mut y) = self.tile_of(from);
+        let (end_x, end_y) = self.tile_of(to);
+        let dx = to.x - from.x;
+        let dy = to.y - from.y;
+        let (step_x, mut t_max_x, t_delta_x) = self.init_axis(x, from.x, dx);
+        let (step_y, mut t_max_y, t_delta_y) = self.init_axis(y, from.y, dy);
+
+        // Number of tiles crossed, bounds the loop even with rounding errors
+        let steps = (end_x - x).abs() + (end_y - y).abs();
+        for _ in 0..steps {
+            if self.is_blocking((x, y)) {
+                return false;
+            }
+            if t_max_x < t_max_y {
+                t_max_x += t_delta_x;
+                x += step_x;
+            } else {
+                t_max_y += t_delta_y;
+                y += step_y;
+            }
+        }
+        !self.is_blocking((x, y))
+    }
+
+    // Returns the step direction on this axis, the ray parameter at which the first tile
+    // boundary is crossed, and the ray parameter needed to cross a whole tile
+    fn init_axis(&self, tile: i32, origin: f32, delta: f32) -> (i32, f32, f32) {
+        if delta > 0.0 {
+            let boundary = (tile + 1) as f32 * self.tile_size;
+            (1, (boundary - origin) / delta, self.tile_size / delta)
+        } else if delta < 0.0 {
+            let boundary = tile as f32 * self.tile_size;
+            (-1, (boundary - origin) / delta, -self.tile_size / delta)
+        } else {
+            (0, ::std::f32::INFINITY, ::std::f32::INFINITY)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use nalgebra::Point2;
+
+    use super::MapGeometry;
+
+    fn geometry(tiles: &[(i32,i32)]) -> MapGeometry {
+        MapGeometry {
+            tile_size: 1.0,
+            blocking: tiles.iter().cloned().collect::<HashSet<_>>(),
+        }
+    }
+
+    #[test]
+    fn wall_blocks_sight() {
+        let geometry = geometry(&[(2,-1),(2,0),(2,1)]);
+        assert!(!geometry.has_line_of_sight(Point2::new(0.5, 0.5), Point2::new(4.5, 0.5)));
+        assert!(!geometry.has_line_of_sight(Point2::new(0.5, 0.5), Point2::new(4.5, 1.5)));
+        assert!(geometry.has_line_of_sight(Point2::new(0.5, 0.5), Point2::new(1.5, 1.5)));
+        assert!(geometry.has_line_of_sight(Point2::new(0.5, 3.5), Point2::new(4.5, 3.5)));
+    }
+
+    #[test]
+    fn diagonal_around_obstacle() {
+        let geometry = geometry(&[(1,0)]);
+        assert!(geometry.has_line_of_sight(Point2::new(0.5, 0.5), Point2::new(1.5, 3.5)));
+        assert!(!geometry.has_line_of_sight(Point2::new(0.5, 0.5), Point2::new(3.5, 0.6)));
+    }
+}
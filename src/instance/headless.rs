@@ -0,0 +1,95 @@
+// Instances that are not run by a thread, their ticks are calculated on demand
+//
+// Used by the replays and the tests
+use std::sync::mpsc::{self,Receiver};
+use std::path::PathBuf;
+
+use id::WeakId;
+use actor::ActorId;
+use entity::Entity;
+use messages::{Command,Notification,Request,EntityOrder};
+use scripts::{AaribaScripts,BehaviourTrees};
+use data::Map;
+
+use super::{Instance,InstanceParameters};
+
+impl Instance {
+    /// Creates an instance without thread nor timer
+    ///
+    /// The requests the instance sends to the Game can be read on the returned receiver
+    pub fn headless(scripts: AaribaScripts,
+                    trees: BehaviourTrees,
+                    map: &Map,
+                    seed: u64,
+                    tick_duration: f32,
+                    ) -> (Instance, Receiver<Request>) {
+        Instance::create_headless(scripts, trees, map, seed, tick_duration, None)
+    }
+
+    /// Same as `headless`, with the inputs and notifications recorded in the directory
+    pub fn recorded_headless(scripts: AaribaScripts,
+                             trees: BehaviourTrees,
+                             map: &Map,
+                             seed: u64,
+                             tick_duration: f32,
+                             directory: PathBuf,
+                             ) -> (Instance, Receiver<Request>) {
+        Instance::create_headless(scripts, trees, map, seed, tick_duration, Some(directory))
+    }
+
+    fn create_headless(scripts: AaribaScripts,
+                       trees: BehaviourTrees,
+                       map: &Map,
+                       seed: u64,
+                       tick_duration: f32,
+                       record_directory: Option<PathBuf>,
+                       ) -> (Instance, Receiver<Request>) {
+        let parameters = InstanceParameters {
+            tick_duration: tick_duration,
+            seed: Some(seed),
+            manual_ticks: true,
+            record_directory: record_directory,
+        };
+        let (request, requests) = mpsc::channel();
+        (Instance::new(request, scripts, trees, map, parameters), requests)
+    }
+
+    /// Calculates one tick, and returns the notifications it generated
+    pub fn step(&mut self) -> &[Notification] {
+        self.calculate_tick();
+        &self.prev_notifications
+    }
+
+    /// Applies a command as if it had been sent by the Game
+    ///
+    /// Returns true if the instance has been shut down
+    pub fn receive(&mut self, command: Command) -> bool {
+        self.apply(command)
+    }
+
+    /// Gives an order to a network actor, as if its client had sent it
+    ///
+    /// The order is executed during the next tick
+    pub fn give_order(&mut self, actor: ActorId, order: EntityOrder) -> bool {
+        match self.actors.get_client_mut(actor) {
+            Some(client) => {
+                client.push_order(order);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_entity(&self, entity: WeakId<Entity>) -> Option<&Entity> {
+        self.entities.get(entity)
+    }
+
+    pub fn get_entity_mut(&mut self, entity: WeakId<Entity>) -> Option<&mut Entity> {
+        self.entities.get_mut(entity)
+    }
+
+    /// Number of ticks calculated since the creation of the instance
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+}
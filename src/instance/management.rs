@@ -2,6 +2,9 @@ use super::Instance;
 use data::{
     EntityManagement,
     SpawnMonster,
+    AiStatistics,
+    AiDebug,
+    Input,
 };
 use id::WeakId;
 use entity::Entity;
@@ -16,15 +19,54 @@ impl Instance {
     }
 
     pub fn spawn_monster(&mut self, monster: SpawnMonster) -> EntityManagement {
-        let id = self.add_fake_ai(monster.monster_class, monster.x, monster.y);
+        let id = self.add_fake_ai(monster);
         self.entities.get(id).unwrap().into_management_representation(self.id, self.map_id)
     }
 
+    /// Calculates one tick, only allowed if the ticks of the instance are driven manually
+    ///
+    /// The players are sent to the Game after each tick, there is no periodic update. Returns
+    /// the number of the calculated tick
+    pub fn manual_tick(&mut self) -> Result<u64,ManualTickError> {
+        if !self.manual_ticks {
+            return Err(ManualTickError::Periodic);
+        }
+        self.calculate_tick();
+        self.send_player_update();
+        Ok(self.tick)
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn get_ai_statistics(&self) -> AiStatistics {
+        self.ai_statistics
+    }
+
+    pub fn get_ai_debug(&self, entity: WeakId<Entity>) -> Result<AiDebug,AiDebugError> {
+        let actor = match self.entities.get(entity) {
+            None => return Err(AiDebugError::NotFound),
+            Some(e) => e.get_actor(),
+        };
+        match actor.and_then(|actor| self.actors.get_ai(actor)) {
+            None => Err(AiDebugError::NotAi),
+            Some(ai) => {
+                let source = self.trees.get_source(ai.get_tree_name()).map(String::from);
+                Ok(ai.debug_info(source))
+            }
+        }
+    }
+
     pub fn remove_entity(&mut self, entity: WeakId<Entity>) -> Result<(),RemoveEntityError> {
         let mut found = false;
         match self.entities.remove_if(entity, |e| { found = true; e.is_monster() }) {
             None => Err(if found { RemoveEntityError::IsPlayer } else { RemoveEntityError::NotFound }),
             Some(e) => {
+                // A refused removal has no effect, only the actual ones are recorded
+                if let Some(ref mut recorder) = self.recorder {
+                    recorder.record(self.tick, Input::RemoveEntity { entity: entity.as_u64() });
+                }
                 // Send back to game?
 
                 let notification = Notification::entity_has_quit(entity.as_u64());
@@ -46,3 +88,13 @@ pub enum RemoveEntityError {
     IsPlayer,
 }
 
+
+pub enum ManualTickError {
+    // The ticks of the instance are triggered by a timer
+    Periodic,
+}
+
+pub enum AiDebugError {
+    NotFound,
+    NotAi,
+}
@@ -5,23 +5,43 @@ use std::io;
 use std::mem;
 use std::time::Duration as StdDuration;
 use std::sync::mpsc::{self,Receiver,Sender};
+use std::sync::Arc;
+use std::path::PathBuf;
 
 use time::{self,Duration,SteadyTime,Tm};
 use schedule_recv;
+use nalgebra::Point2;
+use rand::{self,Rng,SeedableRng,XorShiftRng};
 
 use id::{Id,HasId};
-use entity::{self,Entity,EntityStore};
-use actor::{NetworkActor,ActorId,AiActor};
+use entity::{self,Entity,EntityStore,Projectiles};
+use actor::{NetworkActor,ActorId,AiActor,ThinkOutcome};
 use messages::{self,Command,Notification,Request};
 use scripts::{BehaviourTrees,AaribaScripts};
-use data::{Map,Monster};
+use data::{Map,Monster,SpawnMonster,AiStatistics,RecordingHeader,Input};
 
 pub mod management;
+pub mod geometry;
+pub mod recording;
+pub mod replay;
+mod headless;
+
+use self::geometry::MapGeometry;
+use self::recording::Recorder;
+use self::management::ManualTickError;
 
 lazy_static! {
     static ref GAME_PLAYER_REFRESH_PERIOD: Duration = Duration::seconds(2);
 }
 
+/// Random generator of an instance, the same seed always gives the same simulation
+pub type InstanceRng = XorShiftRng;
+
+pub fn seeded_rng(seed: u64) -> InstanceRng {
+    // A XorShift generator cannot be seeded with zeros only
+    XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9e3779b9, 0x7f4a7c15])
+}
+
 
 #[derive(Debug,Default)]
 struct Actors {
@@ -66,12 +86,31 @@ impl Actors {
         self.internal_actors.remove(&id)
     }
 
+    // Gives a new version of their behaviour tree to all AIs
+    fn reload_trees(&mut self, trees: &BehaviourTrees) {
+        for actor in self.internal_actors.values_mut() {
+            match trees.generate_tree(actor.get_tree_name()) {
+                Some(tree) => actor.replace_tree(tree),
+                None => warn!("Tree {} has disappeared, keeping the old version for AI {}",
+                              actor.get_tree_name(), actor.get_id()),
+            }
+        }
+    }
+
+    fn get_ai(&self, id: ActorId) -> Option<&AiActor> {
+        self.internal_actors.get(&id)
+    }
+
+    fn get_client_mut(&mut self, id: ActorId) -> Option<&mut NetworkActor> {
+        self.external_actors.get_mut(&id)
+    }
+
+    // Each client only receives the notifications about the entities around its own
     fn broadcast_notifications(&mut self,
+                               entities: &EntityStore,
                                notifications: &[Notification]) {
         for client in self.external_actors.values_mut() {
-            for notif in notifications {
-                client.send_message(notif.clone());
-            }
+            client.send_notifications(entities, notifications);
         }
     }
 
@@ -90,14 +129,33 @@ impl Actors {
 
     fn execute_orders(&mut self,
                       entities: &mut EntityStore,
+                      geometry: &MapGeometry,
                       notifications: &mut Vec<Notification>,
-                      previous: &[Notification]) {
-        for (_, actor) in self.external_actors.iter_mut() {
-            actor.execute_orders(entities, notifications, previous);
+                      previous: &[Notification],
+                      rng: &mut InstanceRng,
+                      recorder: &mut Option<Recorder>,
+                      tick: u64) -> AiStatistics {
+        // The actors are always executed in the same order, for the simulation to be reproducible
+        let mut external: Vec<_> = self.external_actors.iter_mut().collect();
+        external.sort_by_key(|&(id, _)| id.as_u64());
+        for (_, actor) in external {
+            actor.execute_orders(entities, notifications, previous, recorder, tick);
         }
-        for (_, actor) in self.internal_actors.iter_mut() {
-            actor.execute_orders(entities, notifications, previous);
+
+        let mut statistics = AiStatistics::default();
+        statistics.tick = tick;
+        let start = SteadyTime::now();
+        let mut internal: Vec<_> = self.internal_actors.iter_mut().collect();
+        internal.sort_by_key(|&(id, _)| id.as_u64());
+        for (_, actor) in internal {
+            match actor.execute_orders(entities, geometry, notifications, previous, rng, tick) {
+                ThinkOutcome::Evaluated => statistics.evaluated += 1,
+                ThinkOutcome::Skipped => statistics.skipped += 1,
+                ThinkOutcome::Asleep => statistics.asleep += 1,
+            }
         }
+        statistics.duration_us = (SteadyTime::now() - start).num_microseconds().unwrap_or(i64::max_value());
+        statistics
     }
 
     fn assign_entity_to_actor(&mut self, actor: ActorId, entity: Id<Entity>) -> bool {
@@ -141,7 +199,9 @@ pub struct Instance {
     id: Id<Instance>,
 
     map_id: Id<Map>,
+    geometry: MapGeometry,
     entities: EntityStore,
+    projectiles: Projectiles,
     actors: Actors,
     request: Sender<Request>,
     last_tick: SteadyTime,
@@ -155,25 +215,75 @@ pub struct Instance {
     created_at: Tm,
 
     tick_duration: f32,
+    // Number of ticks calculated since the creation of the instance
+    tick: u64,
+    ai_statistics: AiStatistics,
+    // All the randomness of the simulation comes from there
+    seed: u64,
+    rng: InstanceRng,
+    manual_ticks: bool,
+    recorder: Option<Recorder>,
+}
+
+/// How an instance runs its simulation
+#[derive(Debug,Clone)]
+pub struct InstanceParameters {
+    // In seconds
+    pub tick_duration: f32,
+    // Random if not set
+    pub seed: Option<u64>,
+    // If set, ticks are only calculated when receiving a Command::Tick, and not periodically
+    pub manual_ticks: bool,
+    // If set, the inputs and notifications of the instance are recorded in this directory
+    pub record_directory: Option<PathBuf>,
 }
 
 impl Instance {
     pub fn spawn_instance(request: Sender<Request>,
                           scripts: AaribaScripts,
                           trees: BehaviourTrees,
-                          map_id: Id<Map>,
-                          tick_duration: f32,
+                          map: Arc<Map>,
+                          parameters: InstanceParameters,
                           ) -> InstanceRef {
-        let mut instance = Instance::new(request, scripts, trees, map_id, tick_duration);
+        use uuid::Uuid;
+
+        let map_id = map.get_id();
+        let tick_duration = parameters.tick_duration;
+        let manual_ticks = parameters.manual_ticks;
+        let mut instance = Instance::new(request, scripts, trees, &map, parameters);
+
+        // XXX Fake an AI on the map
+        let class_str = "67e6001e-d735-461d-b32e-2e545e12b3d2";
+        let uuid = Uuid::parse_str(class_str).unwrap();
+        instance.add_fake_ai(SpawnMonster {
+            monster_class: Id::forge(uuid),
+            x: 0.0,
+            y: 0.0,
+            waypoints: Vec::new(),
+            think_period: None,
+        });
+
         let id = instance.get_id();
         let created_at = instance.created_at;
         let (sender, rx) = mpsc::channel();
+        if manual_ticks {
+            thread::spawn(move || {
+                debug!("Started instance {} with seed {}, ticks are manual", instance.id, instance.seed);
+                for command in rx {
+                    if instance.apply(command) {
+                        break;
+                    }
+                }
+                debug!("Stopping instance {}", instance.id);
+            });
+            return InstanceRef::new(id, sender, created_at, map_id);
+        }
         thread::spawn(move || {
             let tick = schedule_recv::periodic(StdDuration::from_millis((tick_duration * 1000.0) as u64));
             let players_update = schedule_recv::periodic(GAME_PLAYER_REFRESH_PERIOD.to_std().unwrap());
             instance.last_tick = SteadyTime::now();
 
-            debug!("Started instance {}", instance.id);
+            debug!("Started instance {} with seed {}", instance.id, instance.seed);
             loop {
                 select! {
                     _ = tick.recv() => {
@@ -195,12 +305,7 @@ impl Instance {
                         instance.last_tick = current;
                     },
                     _ = players_update.recv() => {
-                        let vec = instance.entities
-                            .iter()
-                            .filter(|e| e.is_player())
-                            .map(|e| e.into_management_representation(instance.id, instance.map_id))
-                            .collect();
-                        instance.request.send(Request::PlayerUpdate(vec)).unwrap();
+                        instance.send_player_update();
                     },
                     command = rx.recv() => {
                         let command = command.unwrap();
@@ -219,33 +324,52 @@ impl Instance {
     fn new(request: Sender<Request>,
            scripts: AaribaScripts,
            trees: BehaviourTrees,
-           map_id: Id<Map>,
-           tick_duration: f32,
+           map: &Map,
+           parameters: InstanceParameters,
            ) -> Instance {
-        use uuid::Uuid;
-
-        let mut instance = Instance {
-            id: Id::new(),
-            map_id: map_id,
+        let id = Id::new();
+        let seed = parameters.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let header = RecordingHeader {
+            map: map.get_id(),
+            seed: seed,
+            tick_duration: parameters.tick_duration,
+        };
+        let recorder = parameters.record_directory.and_then(|directory| {
+            match Recorder::create(&directory, id, &header) {
+                Ok(recorder) => {
+                    debug!("Recording instance {} in {}", id, directory.display());
+                    Some(recorder)
+                }
+                Err(e) => {
+                    error!("Could not record instance {} in {}: {}", id, directory.display(), e);
+                    None
+                }
+            }
+        });
+        Instance {
+            id: id,
+            map_id: map.get_id(),
+            geometry: MapGeometry::new(map),
             entities: EntityStore::new(),
+            projectiles: Projectiles::new(),
             actors: Default::default(),
             request: request,
             last_tick: SteadyTime::now(),
             lag: Duration::zero(),
-            tick_duration: tick_duration,
+            tick_duration: parameters.tick_duration,
             prev_notifications: Default::default(),
             next_notifications: Default::default(),
             scripts: scripts,
             trees: trees,
             shutting_down: false,
             created_at: time::now_utc(),
-        };
-
-        // XXX Fake an AI on the map
-        let class_str = "67e6001e-d735-461d-b32e-2e545e12b3d2";
-        let uuid = Uuid::parse_str(class_str).unwrap();
-        instance.add_fake_ai(Id::forge(uuid), 0.0, 0.0);
-        instance
+            tick: 0,
+            ai_statistics: Default::default(),
+            seed: seed,
+            rng: seeded_rng(seed),
+            manual_ticks: parameters.manual_ticks,
+            recorder: recorder,
+        }
     }
 
     // Apply a command to update the game state.
@@ -253,6 +377,14 @@ impl Instance {
     // returns: true if the instance has been shutdown while executing
     // the command, false otherwise
     fn apply(&mut self, command: Command) -> bool {
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record_command(self.tick, &command);
+        }
+        self.execute(command)
+    }
+
+    // Same as apply, without recording the command
+    fn execute(&mut self, command: Command) -> bool {
         match command {
             Command::NewClient(actor,entities) => {
                 self.register_client(actor, entities);
@@ -269,26 +401,45 @@ impl Instance {
             Command::AssignEntity((actor,entity)) => {
                 self.assign_entity_to_actor(actor, entity);
             }
+            Command::ReloadScripts(scripts, trees) => {
+                self.reload_scripts(scripts, trees);
+            }
+            Command::Tick => {
+                // A periodic instance must stay on its schedule
+                if let Err(ManualTickError::Periodic) = self.manual_tick() {
+                    warn!("Instance {}: refused a manual tick, the ticks are periodic", self.id);
+                }
+            }
         }
 
         self.shutting_down
     }
 
+    // Keeps the Game informed of the state of the players, for the management API
+    fn send_player_update(&self) {
+        let vec = self.entities
+            .iter()
+            .filter(|e| e.is_player())
+            .map(|e| e.into_management_representation(self.id, self.map_id))
+            .collect();
+        self.request.send(Request::PlayerUpdate(vec)).unwrap();
+    }
+
+    fn reload_scripts(&mut self, scripts: AaribaScripts, trees: BehaviourTrees) {
+        debug!("Instance {}: reloading scripts", self.id);
+        self.actors.reload_trees(&trees);
+        self.scripts = scripts;
+        self.trees = trees;
+    }
+
     fn register_client(
         &mut self,
-        mut actor: NetworkActor,
+        actor: NetworkActor,
         entities: Vec<Entity>,
         ) {
         let id = actor.get_id();
         trace!("Registering actor {} in instance {}", id, self.id);
-        for entity in self.entities.iter() {
-            let position = entity.get_position();
-            let skin = entity.get_skin();
-            let entity_id = entity.get_id().as_u64();
-            let pv = entity.get_pv();
-            let notification = Notification::new_entity(entity_id, position, skin, pv);
-            actor.send_message(notification);
-        }
+        // The entities around are sent to the client with the notifications of the next tick
         for entity in entities {
             let entity_id = entity.get_id().as_u64();
             let position = entity.get_position();
@@ -376,20 +527,39 @@ impl Instance {
 
     fn calculate_tick(&mut self) {
         trace!("Instance {}: Calculating tick\n{}", self.id, self);
-        self.actors.execute_orders(&mut self.entities,
-                                   &mut self.next_notifications,
-                                   &self.prev_notifications);
-
-        let events = entity::update(&mut self.entities, &mut self.next_notifications, &self.scripts, self.tick_duration);
+        self.tick += 1;
+        self.ai_statistics = self.actors.execute_orders(&mut self.entities,
+                                                        &self.geometry,
+                                                        &mut self.next_notifications,
+                                                        &self.prev_notifications,
+                                                        &mut self.rng,
+                                                        &mut self.recorder,
+                                                        self.tick);
+        trace!("Instance {}: AI statistics {:?}", self.id, self.ai_statistics);
+
+        let events = entity::update(&mut self.entities,
+                                    &mut self.projectiles,
+                                    &self.geometry,
+                                    &mut self.next_notifications,
+                                    &self.scripts,
+                                    &mut self.rng,
+                                    self.tick_duration);
         for event in events {
             self.process_event(event);
         }
 
         let commands_buffer = self.actors.get_commands();
         for command in commands_buffer {
-            self.apply(command);
+            // The actors generate these commands again when replaying
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.record_actor_command(self.tick, &command);
+            }
+            self.execute(command);
+        }
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record_notifications(self.tick, &self.next_notifications);
         }
-        self.actors.broadcast_notifications(&self.next_notifications);
+        self.actors.broadcast_notifications(&self.entities, &self.next_notifications);
         debug!("Notifications: {:?}", self.next_notifications);
         self.prev_notifications.clear();
         mem::swap(&mut self.prev_notifications, &mut self.next_notifications);
@@ -413,12 +583,38 @@ impl Instance {
         }
     }
 
-    fn add_fake_ai(&mut self, class: Id<Monster>, x: f32, y: f32) -> Id<Entity> {
-        let ai = AiActor::fake(self.trees.generate_tree("zombie").unwrap());
+    fn add_fake_ai(&mut self, monster: SpawnMonster) -> Id<Entity> {
+        let (ai, entity) = self.create_fake_ai(&monster);
+        self.add_ai(monster, ai, entity)
+    }
+
+    fn create_fake_ai(&self, monster: &SpawnMonster) -> (AiActor, Entity) {
+        let tree_name = "zombie";
+        let ai = AiActor::fake(String::from(tree_name), self.trees.generate_tree(tree_name).unwrap());
+        let entity = Entity::fake_ai(monster.monster_class, monster.x, monster.y);
+        (ai, entity)
+    }
+
+    fn add_ai(&mut self, monster: SpawnMonster, mut ai: AiActor, mut entity: Entity) -> Id<Entity> {
         let id = ai.get_id();
+        if let Some(ref mut recorder) = self.recorder {
+            let input = Input::SpawnMonster {
+                actor: id.as_u64(),
+                entity: entity.get_id().as_u64(),
+                skin: entity.get_skin(),
+                monster: monster.clone(),
+            };
+            recorder.record(self.tick, input);
+        }
+        let (x, y) = (monster.x, monster.y);
+        let waypoints = monster.waypoints.iter().map(|w| Point2::new(w.x, w.y)).collect();
+        ai.set_home(Point2::new(x, y), waypoints);
+        if let Some(think_period) = monster.think_period {
+            ai.set_think_period(think_period);
+        }
+        ai.set_think_offset(self.rng.gen::<u32>() as u64);
         self.actors.register_internal(ai);
 
-        let mut entity = Entity::fake_ai(class, x, y);
         entity.set_actor(Some(id));
         let entity_id = entity.get_id();
         self.assign_entity_to_actor(id, entity);
@@ -0,0 +1,264 @@
+// Recording of the inputs of an instance
+//
+// Every command and order applied to an instance is written to a file, tagged with its tick.
+// Together with the seed of the instance, this is enough to replay the exact same simulation.
+// The notifications of each tick are written to a second file, so that a replay can be
+// compared with the original (see the `lycan-replay` binary)
+use std::fs::File;
+use std::io::{self,BufRead,BufReader,BufWriter,Write};
+use std::path::{Path,PathBuf};
+use std::fmt::{self,Display,Formatter};
+
+use serde_json;
+
+use id::Id;
+use actor::ActorId;
+use entity::Entity;
+use messages::{Command,Notification,EntityOrder,Order,Direction,NetworkCastTarget};
+use data::{
+    RecordingHeader,
+    RecordedInput,
+    Input,
+    RecordedPlayer,
+    RecordedOrder,
+    RecordedDirection,
+    RecordedCastTarget,
+};
+
+use super::Instance;
+
+pub struct Recorder {
+    inputs: BufWriter<File>,
+    notifications: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Creates `INSTANCE.recording` and `INSTANCE.notifications` in the directory
+    pub fn create(directory: &Path,
+                  instance: Id<Instance>,
+                  header: &RecordingHeader,
+                  ) -> io::Result<Recorder> {
+        let mut inputs = BufWriter::new(try!(File::create(recording_path(directory, instance))));
+        let notifications = BufWriter::new(try!(File::create(notifications_path(directory, instance))));
+        let serialized = try!(serde_json::to_string(header)
+                              .map_err(|e| io::Error::new(io::ErrorKind::Other, e)));
+        try!(writeln!(inputs, "{}", serialized));
+        Ok(Recorder {
+            inputs: inputs,
+            notifications: notifications,
+        })
+    }
+
+    pub fn record(&mut self, tick: u64, input: Input) {
+        let recorded = RecordedInput {
+            tick: tick,
+            input: input,
+        };
+        let result = serde_json::to_string(&recorded)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+            .and_then(|serialized| writeln!(self.inputs, "{}", serialized));
+        if let Err(e) = result {
+            error!("Could not record input {:?}: {}", recorded, e);
+        }
+    }
+
+    /// Records a command received by the instance
+    ///
+    /// Arbitrary commands cannot be recorded, the management functions they call record their
+    /// effects instead
+    pub fn record_command(&mut self, tick: u64, command: &Command) {
+        let input = match *command {
+            Command::NewClient(ref actor, ref entities) => {
+                Input::NewClient {
+                    actor: actor.get_id().as_u64(),
+                    entities: entities.iter().filter_map(record_player).collect(),
+                }
+            }
+            Command::UnregisterActor(actor) => {
+                Input::UnregisterActor {
+                    actor: actor.as_u64(),
+                }
+            }
+            Command::AssignEntity((actor, ref entity)) => {
+                match record_player(entity) {
+                    Some(entity) => {
+                        Input::AssignEntity {
+                            actor: actor.as_u64(),
+                            entity: entity,
+                        }
+                    }
+                    None => {
+                        warn!("Could not record the assignation of entity {}", entity.get_id());
+                        return;
+                    }
+                }
+            }
+            Command::ReloadScripts(..) => Input::ReloadScripts,
+            Command::Shutdown => Input::Shutdown,
+            Command::Arbitrary(_) | Command::Tick => return,
+        };
+        self.record(tick, input);
+    }
+
+    /// Records a command generated by an actor during a tick
+    pub fn record_actor_command(&mut self, tick: u64, command: &Command) {
+        match *command {
+            Command::UnregisterActor(actor) => {
+                self.record(tick, Input::Disconnect { actor: actor.as_u64() });
+            }
+            ref other => warn!("Could not record command {:?} generated by an actor", other),
+        }
+    }
+
+    pub fn record_order(&mut self, tick: u64, actor: ActorId, order: &EntityOrder) {
+        let input = Input::Order {
+            actor: actor.as_u64(),
+            entity: order.entity,
+            order: record_order(&order.order),
+        };
+        self.record(tick, input);
+    }
+
+    /// Writes the notifications of a tick, and flushes the recording
+    pub fn record_notifications(&mut self, tick: u64, notifications: &[Notification]) {
+        let result = write_notifications(&mut self.notifications, tick, notifications)
+            .and_then(|()| self.notifications.flush())
+            .and_then(|()| self.inputs.flush());
+        if let Err(e) = result {
+            error!("Could not record the notifications of tick {}: {}", tick, e);
+        }
+    }
+}
+
+pub fn recording_path(directory: &Path, instance: Id<Instance>) -> PathBuf {
+    directory.join(format!("{}.recording", instance))
+}
+
+pub fn notifications_path(directory: &Path, instance: Id<Instance>) -> PathBuf {
+    directory.join(format!("{}.notifications", instance))
+}
+
+/// The format is the same for a recording and a replay, so that they can be diffed
+pub fn write_notifications<W: Write>(output: &mut W,
+                                     tick: u64,
+                                     notifications: &[Notification],
+                                     ) -> io::Result<()> {
+    for notification in notifications {
+        try!(writeln!(output, "{} {:?}", tick, notification));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub inputs: Vec<RecordedInput>,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    Empty,
+    // Line number and error
+    Parsing(usize, serde_json::Error),
+}
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            RecordingError::Io(ref e) => write!(f, "could not read the recording: {}", e),
+            RecordingError::Empty => write!(f, "the recording is empty"),
+            RecordingError::Parsing(line, ref e) => write!(f, "invalid recording at line {}: {}", line, e),
+        }
+    }
+}
+
+pub fn read_recording(path: &Path) -> Result<Recording,RecordingError> {
+    let file = try!(File::open(path).map_err(RecordingError::Io));
+    let mut lines = BufReader::new(file).lines();
+    let header = match lines.next() {
+        None => return Err(RecordingError::Empty),
+        Some(line) => {
+            let line = try!(line.map_err(RecordingError::Io));
+            try!(serde_json::from_str(&line).map_err(|e| RecordingError::Parsing(1, e)))
+        }
+    };
+    let mut inputs = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line = try!(line.map_err(RecordingError::Io));
+        // The header is the first line
+        let input = try!(serde_json::from_str(&line).map_err(|e| RecordingError::Parsing(index + 2, e)));
+        inputs.push(input);
+    }
+    Ok(Recording {
+        header: header,
+        inputs: inputs,
+    })
+}
+
+fn record_player(entity: &Entity) -> Option<RecordedPlayer> {
+    entity.to_player().map(|player| {
+        RecordedPlayer {
+            entity: entity.get_id().as_u64(),
+            player: player,
+        }
+    })
+}
+
+fn record_order(order: &Order) -> RecordedOrder {
+    match *order {
+        Order::Walk(direction) => RecordedOrder::Walk(direction.map(record_direction)),
+        Order::Say(ref message) => RecordedOrder::Say(message.clone()),
+        Order::Attack => RecordedOrder::Attack,
+        Order::Cast { ref skill, ref target } => {
+            let target = match *target {
+                NetworkCastTarget::Direction(direction) => {
+                    RecordedCastTarget::Direction(record_direction(direction))
+                }
+                NetworkCastTarget::Entity(entity) => RecordedCastTarget::Entity(entity),
+            };
+            RecordedOrder::Cast {
+                skill: skill.clone(),
+                target: target,
+            }
+        }
+    }
+}
+
+fn record_direction(direction: Direction) -> RecordedDirection {
+    match direction {
+        Direction::North => RecordedDirection::North,
+        Direction::South => RecordedDirection::South,
+        Direction::East => RecordedDirection::East,
+        Direction::West => RecordedDirection::West,
+    }
+}
+
+pub fn recorded_order(order: RecordedOrder) -> Order {
+    match order {
+        RecordedOrder::Walk(direction) => Order::Walk(direction.map(recorded_direction)),
+        RecordedOrder::Say(message) => Order::Say(message),
+        RecordedOrder::Attack => Order::Attack,
+        RecordedOrder::Cast { skill, target } => {
+            let target = match target {
+                RecordedCastTarget::Direction(direction) => {
+                    NetworkCastTarget::Direction(recorded_direction(direction))
+                }
+                RecordedCastTarget::Entity(entity) => NetworkCastTarget::Entity(entity),
+            };
+            Order::Cast {
+                skill: skill,
+                target: target,
+            }
+        }
+    }
+}
+
+fn recorded_direction(direction: RecordedDirection) -> Direction {
+    match direction {
+        RecordedDirection::North => Direction::North,
+        RecordedDirection::South => Direction::South,
+        RecordedDirection::East => Direction::East,
+        RecordedDirection::West => Direction::West,
+    }
+}
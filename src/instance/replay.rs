@@ -0,0 +1,173 @@
+// Replay of a recording in a headless instance
+//
+// The instance is created with the seed of the recording, and the recorded inputs are applied
+// at the same point of the same ticks. Entities and actors get back their recorded ids, so the
+// notifications are the same as during the recording
+use std::io::{self,Write};
+use std::path::Path;
+use std::fmt::{self,Display,Formatter};
+
+use id::{Id,WeakId};
+use actor::NetworkActor;
+use entity::Entity;
+use messages::{Command,EntityOrder};
+use scripts::{self,AaribaScripts,BehaviourTrees};
+use data::{Map,Input,RecordedPlayer,UNIQUE_MAP};
+use utils::ConfigurationSource;
+
+use super::Instance;
+use super::recording::{self,Recording,RecordingError};
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Recording(RecordingError),
+    Scripts(scripts::Error),
+    UnknownMap(Id<Map>),
+    Output(io::Error),
+    // The recorded scripts are not known, the rest of the replay would diverge
+    ScriptsReloaded(u64),
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            ReplayError::Recording(ref e) => write!(f, "{}", e),
+            ReplayError::Scripts(ref e) => write!(f, "could not load the scripts: {}", e),
+            ReplayError::UnknownMap(id) => write!(f, "unknown map {}", id),
+            ReplayError::Output(ref e) => write!(f, "could not write the notifications: {}", e),
+            ReplayError::ScriptsReloaded(tick) => {
+                write!(f, "the scripts were reloaded during tick {}, which cannot be replayed", tick)
+            }
+        }
+    }
+}
+
+/// Replays the recording, with the scripts of the given configuration
+///
+/// The notifications are written in the same format as the ones of the recording. Unless a
+/// number of ticks is given, the replay stops after the last recorded input. The replay fails
+/// when it reaches a reload of the scripts, after writing the notifications of the ticks before it
+pub fn replay_file<W: Write>(recording: &Path,
+                             configuration: &str,
+                             ticks: Option<u64>,
+                             output: &mut W,
+                             ) -> Result<(),ReplayError> {
+    let recording = try!(recording::read_recording(recording).map_err(ReplayError::Recording));
+    let configuration = ConfigurationSource::new(configuration);
+    let (scripts, trees) = try!(scripts::load_scripts(&configuration).map_err(ReplayError::Scripts));
+    replay(recording, scripts, trees, ticks, output)
+}
+
+pub fn replay<W: Write>(recording: Recording,
+                        scripts: AaribaScripts,
+                        trees: BehaviourTrees,
+                        ticks: Option<u64>,
+                        output: &mut W,
+                        ) -> Result<(),ReplayError> {
+    // XXX: Only one map for now
+    if recording.header.map != UNIQUE_MAP.get_id() {
+        return Err(ReplayError::UnknownMap(recording.header.map));
+    }
+    // Nobody listens to the requests of a replayed instance, but they must not fail
+    let (mut instance, _requests) = Instance::headless(scripts,
+                                                       trees,
+                                                       &UNIQUE_MAP,
+                                                       recording.header.seed,
+                                                       recording.header.tick_duration);
+
+    let last_tick = ticks.unwrap_or_else(|| recording.inputs.last().map(|i| i.tick).unwrap_or(0));
+    for recorded in recording.inputs {
+        // Orders must be given to the actors before the tick during which they were executed
+        let due = if applied_during_tick(&recorded.input) {
+            recorded.tick.saturating_sub(1)
+        } else {
+            recorded.tick
+        };
+        if due > last_tick {
+            break;
+        }
+        while instance.tick < due {
+            try!(replay_tick(&mut instance, output));
+        }
+        if try!(replay_input(&mut instance, recorded.tick, recorded.input)) {
+            return Ok(());
+        }
+    }
+    while instance.tick < last_tick {
+        try!(replay_tick(&mut instance, output));
+    }
+    Ok(())
+}
+
+fn applied_during_tick(input: &Input) -> bool {
+    match *input {
+        Input::Order { .. } | Input::Disconnect { .. } => true,
+        _ => false,
+    }
+}
+
+fn replay_tick<W: Write>(instance: &mut Instance, output: &mut W) -> Result<(),ReplayError> {
+    instance.calculate_tick();
+    recording::write_notifications(output, instance.tick, &instance.prev_notifications)
+        .map_err(ReplayError::Output)
+}
+
+// Returns true if the instance was shut down
+fn replay_input(instance: &mut Instance, tick: u64, input: Input) -> Result<bool,ReplayError> {
+    match input {
+        Input::NewClient { actor, entities } => {
+            let mut actor = NetworkActor::replayed(Id::forge(actor));
+            let entities: Vec<_> = entities.into_iter().map(restore_player).collect();
+            for entity in entities.iter() {
+                actor.register_entity(entity.get_id());
+            }
+            instance.execute(Command::NewClient(actor, entities));
+        }
+        Input::UnregisterActor { actor } => {
+            instance.execute(Command::UnregisterActor(Id::forge(actor)));
+        }
+        Input::AssignEntity { actor, entity } => {
+            instance.execute(Command::AssignEntity((Id::forge(actor), restore_player(entity))));
+        }
+        Input::SpawnMonster { actor, entity, skin, monster } => {
+            let (mut ai, mut restored) = instance.create_fake_ai(&monster);
+            ai.restore_id(Id::forge(actor));
+            restored.restore_identity(Id::forge(entity), skin);
+            instance.add_ai(monster, ai, restored);
+        }
+        Input::RemoveEntity { entity } => {
+            let _ = instance.remove_entity(WeakId::new(entity));
+        }
+        Input::ReloadScripts => {
+            return Err(ReplayError::ScriptsReloaded(tick));
+        }
+        Input::Shutdown => {
+            return Ok(true);
+        }
+        Input::Order { actor, entity, order } => {
+            match instance.actors.get_client_mut(Id::forge(actor)) {
+                Some(client) => {
+                    client.push_order(EntityOrder {
+                        entity: entity,
+                        order: recording::recorded_order(order),
+                    });
+                }
+                None => warn!("Order given to missing actor {}", actor),
+            }
+        }
+        Input::Disconnect { actor } => {
+            match instance.actors.get_client_mut(Id::forge(actor)) {
+                Some(client) => client.disconnect(),
+                None => warn!("Disconnection of missing actor {}", actor),
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn restore_player(recorded: RecordedPlayer) -> Entity {
+    let skin = recorded.player.skin;
+    let mut entity = Entity::from(recorded.player);
+    entity.restore_identity(Id::forge(recorded.entity), skin);
+    entity
+}
@@ -49,3 +49,6 @@ mod scripts;
 mod network;
 
 pub mod messages;
+
+#[cfg(test)]
+mod tests;
@@ -14,9 +14,13 @@ Usage:
     lycan [options]
 
 Options:
-    -c URL, --configuration URL     URL of the configuration server [default: http://localhost:9000]
+    -c URL, --configuration URL     URL of the configuration server, or local directory [default: http://localhost:9000]
     -p PORT, --port PORT            Listening port [default: 7777]
     -t TICK, --tick TICK            Server tick duration in ms [default: 50]
+    -w PERIOD, --watch PERIOD       Reload the scripts when they change, checking every PERIOD seconds (0 to disable) [default: 0]
+    --seed SEED                     Seed of the random generator of the instances
+    --manual-ticks                  Only calculate a tick when requested by the management API
+    --record DIR                    Record the inputs of the instances in DIR, to replay them with lycan-replay
     -h, --help                      Prints this message
 "#;
 
@@ -25,6 +29,10 @@ struct Args {
     flag_port: u16,
     flag_configuration: String,
     flag_tick: f32,
+    flag_watch: f32,
+    flag_seed: Option<u64>,
+    flag_manual_ticks: bool,
+    flag_record: Option<String>,
 }
 
 fn main() {
@@ -39,8 +47,18 @@ fn main() {
         port: args.flag_port,
         configuration_url: args.flag_configuration.clone(),
         tick_duration: args.flag_tick / 1000.0,
+        seed: args.flag_seed,
+        manual_ticks: args.flag_manual_ticks,
+        record_directory: args.flag_record.clone(),
+        script_watch_period: args.flag_watch,
+    };
+    let _request = match Game::spawn_game(parameters) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = writeln!(std::io::stderr(), "Error: {}", e);
+            std::process::exit(1);
+        }
     };
-    let _request = Game::spawn_game(parameters);
     println!("Started game with parameters {:#?}", args);
 
     print!("Enter q to quit: ");
@@ -5,11 +5,13 @@ use lycan_serialize::Command as NetworkCommand;
 use lycan_serialize::GameCommand as NetworkGameCommand;
 use lycan_serialize::Direction;
 use lycan_serialize::Vec2d;
+use lycan_serialize::HitOutcome as NetworkHitOutcome;
+use lycan_serialize::DamageType as NetworkDamageType;
 
 use std::fmt::{self,Formatter,Debug};
 use std::boxed::FnBox;
 
-use entity::{Entity};
+use entity::{Entity,HitOutcome,DamageType};
 use game::Game;
 use id::Id;
 use instance::{Instance,ShuttingDownState};
@@ -56,6 +58,11 @@ impl Into<Option<NetworkNotification>> for Notification {
                                                    Vec2d{x: position.x, y: position.y},
                                                    Vec2d{x: speed.x, y: speed.y},
                                                    pv)),
+            Notification::StateUpdate{entity,position,speed,pv} =>
+                Some(NetworkNotification::state_update(entity,
+                                                       position.map(|p| Vec2d{x: p.x, y: p.y}),
+                                                       speed.map(|s| Vec2d{x: s.x, y: s.y}),
+                                                       pv)),
             Notification::ThisIsYou{entity} =>
                 Some(NetworkNotification::this_is_you(entity)),
             Notification::NewEntity{entity,position,skin,pv} =>
@@ -65,14 +72,50 @@ impl Into<Option<NetworkNotification>> for Notification {
                                                      pv)),
             Notification::EntityHasQuit{entity} => 
                 Some(NetworkNotification::entity_has_quit(entity)),
-            Notification::Damage{..} => {
-                // XXX: Need to send that to the network
-                None
-            }
+            Notification::Damage{source,victim,amount,outcome,damage_type} =>
+                Some(NetworkNotification::damage(source,
+                                                 victim,
+                                                 amount,
+                                                 outcome.into(),
+                                                 damage_type.into())),
             Notification::Death{..} => {
                 // XXX: Need to send that to the network
                 None
             }
+            Notification::CastStarted{entity,skill,duration} =>
+                Some(NetworkNotification::cast_started(entity, skill, duration)),
+            Notification::CastInterrupted{entity,skill} =>
+                Some(NetworkNotification::cast_interrupted(entity, skill)),
+            Notification::ProjectileSpawned{projectile,owner,position,velocity} =>
+                Some(NetworkNotification::projectile_spawned(projectile,
+                                                             owner,
+                                                             Vec2d{x: position.x, y: position.y},
+                                                             Vec2d{x: velocity.x, y: velocity.y})),
+            Notification::ProjectileDespawned{projectile} =>
+                Some(NetworkNotification::projectile_despawned(projectile)),
+        }
+    }
+}
+
+impl From<HitOutcome> for NetworkHitOutcome {
+    fn from(outcome: HitOutcome) -> NetworkHitOutcome {
+        match outcome {
+            HitOutcome::Hit => NetworkHitOutcome::Hit,
+            HitOutcome::Critical => NetworkHitOutcome::Critical,
+            HitOutcome::Miss => NetworkHitOutcome::Miss,
+            HitOutcome::Dodge => NetworkHitOutcome::Dodge,
+        }
+    }
+}
+
+impl From<DamageType> for NetworkDamageType {
+    fn from(damage_type: DamageType) -> NetworkDamageType {
+        match damage_type {
+            DamageType::Physical => NetworkDamageType::Physical,
+            DamageType::Fire => NetworkDamageType::Fire,
+            DamageType::Ice => NetworkDamageType::Ice,
+            DamageType::Lightning => NetworkDamageType::Lightning,
+            DamageType::Poison => NetworkDamageType::Poison,
         }
     }
 }
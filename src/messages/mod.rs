@@ -4,6 +4,7 @@ pub use lycan_serialize::EntityOrder as NetworkEntityOrder;
 pub use lycan_serialize::Command as NetworkCommand;
 pub use lycan_serialize::GameCommand as NetworkGameCommand;
 pub use lycan_serialize::Direction;
+pub use lycan_serialize::CastTarget as NetworkCastTarget;
 
 // TODO REMOVE
 pub use lycan_serialize::Order;
@@ -15,13 +16,14 @@ use std::sync::mpsc::Sender;
 
 use nalgebra::{Point2,Vector2};
 
-use entity::{Entity};
+use entity::{Entity,DamageType,HitOutcome};
 use game::Game;
 use actor::{NetworkActor,ActorId};
 use id::Id;
 use instance::{Instance,ShuttingDownState};
 use data::EntityManagement;
 use network::Client;
+use scripts::{AaribaScripts,BehaviourTrees};
 
 mod conversions;
 
@@ -32,6 +34,9 @@ pub enum Command {
     Arbitrary(Arbitrary<Instance>),
     UnregisterActor(ActorId),
     AssignEntity((ActorId,Entity)),
+    ReloadScripts(AaribaScripts,BehaviourTrees),
+    // Calculates one tick, for instances whose ticks are driven manually
+    Tick,
 }
 
 impl Command {
@@ -102,6 +107,13 @@ pub enum Notification {
         speed: Vector2<f32>,
         pv: u64,
     },
+    // Only the fields that changed since the last state sent to the client
+    StateUpdate {
+        entity: u64,
+        position: Option<Point2<f32>>,
+        speed: Option<Vector2<f32>>,
+        pv: Option<u64>,
+    },
     ThisIsYou {
         entity: u64,
     },
@@ -118,10 +130,31 @@ pub enum Notification {
         source: u64,
         victim: u64,
         amount: u64,
+        outcome: HitOutcome,
+        damage_type: DamageType,
     },
     Death {
         entity: u64,
     },
+    CastStarted {
+        entity: u64,
+        skill: String,
+        // In seconds
+        duration: f32,
+    },
+    CastInterrupted {
+        entity: u64,
+        skill: String,
+    },
+    ProjectileSpawned {
+        projectile: u64,
+        owner: u64,
+        position: Point2<f32>,
+        velocity: Vector2<f32>,
+    },
+    ProjectileDespawned {
+        projectile: u64,
+    },
 }
 
 pub enum GameCommand {}
@@ -97,20 +97,31 @@ impl ::std::fmt::Debug for Client {
     }
 }
 
-pub fn start_server(addr: SocketAddr, tx: StdSender<Request>) {
+/// Starts the network thread, listening for clients on `addr`
+///
+/// Returns once the socket is bound, or if it failed to do so
+pub fn start_server(addr: SocketAddr, tx: StdSender<Request>) -> Result<(),IoError> {
     let builder = thread::Builder::new()
         .name("Network Tokio".into());
+    let (bound_tx, bound_rx) = mpsc::channel();
 
-    builder.spawn(move || {
+    try!(builder.spawn(move || {
         // Create the event loop that will drive this server
-        let mut l = Core::new().unwrap();
+        let mut l = match Core::new() {
+            Ok(l) => l,
+            Err(e) => { let _ = bound_tx.send(Err(e)); return; }
+        };
         let handle = l.handle();
 
         // Create a TCP listener which will listen for incoming connections
-        let socket = TcpListener::bind(&addr, &handle).unwrap();
+        let socket = match TcpListener::bind(&addr, &handle) {
+            Ok(socket) => socket,
+            Err(e) => { let _ = bound_tx.send(Err(e)); return; }
+        };
 
         // Once we've got the TCP listener, inform that we have it
         println!("Listening on: {}", addr);
+        let _ = bound_tx.send(Ok(()));
 
         let done = socket.incoming().for_each(|(socket, _addr)| {
             handle_client(socket, &handle, tx.clone());
@@ -124,7 +135,12 @@ pub fn start_server(addr: SocketAddr, tx: StdSender<Request>) {
         // There are currently no clean way to stop the event loop, so this
         // function currently never returns
         l.run(done).unwrap();
-    }).unwrap();
+    }));
+
+    match bound_rx.recv() {
+        Ok(result) => result,
+        Err(_) => Err(IoError::new(ErrorKind::Other, "The network thread stopped during startup")),
+    }
 }
 
 // Handles an incomming client on the network
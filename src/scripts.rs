@@ -1,80 +1,211 @@
 // Aariba scripts and behaviour trees used by Lycan
 use std::collections::HashMap;
+use std::fmt;
 
 use aariba::rules::RulesEvaluator;
 use aariba;
-use hyper::error::Error as HyperError;
 
 use behaviour_tree::tree::factory::TreeFactory;
 use behaviour_tree::tree::{LeafNodeFactory,BehaviourTreeNode};
 use behaviour_tree;
 
+use serde_json;
+
 use ai::{Context,ActionNode,ActionNodeFactory,LeavesCollection};
-use utils;
+use data::{ScriptManifest,Skill};
+use utils::{ConfigurationSource,LoadError};
 
 pub type BehaviourTreeFactory = TreeFactory<ActionNodeFactory>;
 pub type BehaviourTree = behaviour_tree::BehaviourTree<ActionNode>;
 
 #[derive(Debug,Clone)]
 pub struct AaribaScripts {
-    pub combat: RulesEvaluator,
+    rules: HashMap<String, RulesEvaluator>,
+    // Content of the script files, to detect modifications
+    sources: HashMap<String, String>,
+    // Skills are kept with the rule sets, as each of them refers to an effect rule set
+    skills: HashMap<String, Skill>,
+    // Content of the skill files
+    skill_sources: HashMap<String, String>,
 }
 
+// Rule sets that must be present for the game to work
+const REQUIRED_RULES: &'static [&'static str] = &["combat"];
+
+const MANIFEST: &'static str = "manifest.json";
+
 #[derive(Debug)]
 pub enum Error {
-    Hyper(HyperError),
+    Load(String, LoadError),
+    Manifest(String),
     AaribaParsing(String),
     BehaviourTreeParsing(String),
+    MissingRules(String),
+    SkillParsing(String),
+    UnknownEffect {
+        skill: String,
+        effect: String,
+    },
+    DuplicateSkill(String),
+    DuplicateTree {
+        name: String,
+        first_file: String,
+        second_file: String,
+    },
 }
 
-impl From<HyperError> for Error {
-    fn from(e: HyperError) -> Error {
-        Error::Hyper(e)
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            Error::Load(ref file, ref e) => write!(f, "could not fetch script {}: {}", file, e),
+            Error::Manifest(ref e) => write!(f, "invalid script manifest: {}", e),
+            Error::AaribaParsing(ref e) => write!(f, "aariba parsing error: {}", e),
+            Error::BehaviourTreeParsing(ref e) => write!(f, "behaviour tree parsing error: {}", e),
+            Error::MissingRules(ref name) => write!(f, "missing aariba rule set {}", name),
+            Error::SkillParsing(ref e) => write!(f, "skill parsing error: {}", e),
+            Error::UnknownEffect { ref skill, ref effect } => {
+                write!(f, "skill {} refers to unknown aariba rule set {}", skill, effect)
+            }
+            Error::DuplicateSkill(ref name) => write!(f, "skill {} defined several times", name),
+            Error::DuplicateTree { ref name, ref first_file, ref second_file } => {
+                write!(f, "behaviour tree {} defined both in {} and {}", name, first_file, second_file)
+            }
+        }
     }
 }
 
+/// Fetches the manifest, then parses all the scripts it lists
+pub fn load_scripts(source: &ConfigurationSource) -> Result<(AaribaScripts,BehaviourTrees),Error> {
+    let manifest = try!(get_manifest(source));
+    let scripts = try!(AaribaScripts::load(source, &manifest));
+    let trees = try!(BehaviourTrees::load(source, &manifest));
+    Ok((scripts, trees))
+}
+
+fn get_manifest(source: &ConfigurationSource) -> Result<ScriptManifest,Error> {
+    let content = try!(get_file(source, MANIFEST));
+    serde_json::from_str(&content).map_err(|e| Error::Manifest(e.to_string()))
+}
+
+fn get_file(source: &ConfigurationSource, file: &str) -> Result<String,Error> {
+    source.get_file(file).map_err(|e| Error::Load(String::from(file), e))
+}
+
 impl AaribaScripts {
-    pub fn get_from_url(base_url: &str) -> Result<AaribaScripts,Error> {
-        let mut url = String::from(base_url);
-        url.push_str("/combat.aariba");
-        debug!("Getting file {}", url);
-        let script = try!(utils::get_file_from_url(&url));
+    pub fn new() -> AaribaScripts {
+        AaribaScripts {
+            rules: HashMap::new(),
+            sources: HashMap::new(),
+            skills: HashMap::new(),
+            skill_sources: HashMap::new(),
+        }
+    }
+
+    pub fn load(source: &ConfigurationSource, manifest: &ScriptManifest) -> Result<AaribaScripts,Error> {
+        let mut scripts = AaribaScripts::new();
+        for (name, file) in manifest.aariba.iter() {
+            let script = try!(get_file(source, file));
+            try!(scripts.append(name, file, script));
+        }
+        for name in REQUIRED_RULES {
+            if !scripts.rules.contains_key(*name) {
+                return Err(Error::MissingRules(name.to_string()));
+            }
+        }
+        for file in manifest.skills.iter() {
+            let content = try!(get_file(source, file));
+            let parsed_skills: Vec<Skill> =
+                try!(serde_json::from_str(&content)
+                    .map_err(|e| Error::SkillParsing(format!("{}: {}", file, e))));
+            for skill in parsed_skills {
+                if !scripts.rules.contains_key(&skill.effect) {
+                    return Err(Error::UnknownEffect {
+                        skill: skill.name,
+                        effect: skill.effect,
+                    });
+                }
+                if scripts.skills.contains_key(&skill.name) {
+                    return Err(Error::DuplicateSkill(skill.name));
+                }
+                scripts.skills.insert(skill.name.clone(), skill);
+            }
+            scripts.skill_sources.insert(file.clone(), content);
+        }
+        Ok(scripts)
+    }
+
+    /// Parses the rule set defined in the script, and adds it to the collection
+    pub fn append(&mut self, name: &str, file: &str, script: String) -> Result<(),Error> {
         let parsed_script =
             try!(aariba::parse_rule(&script)
-                .map_err(Error::AaribaParsing));
-        let scripts = AaribaScripts {
-            combat: parsed_script,
-        };
-        Ok(scripts)
+                .map_err(|e| Error::AaribaParsing(format!("{}: {}", file, e))));
+        self.rules.insert(String::from(name), parsed_script);
+        self.sources.insert(String::from(name), script);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RulesEvaluator> {
+        self.rules.get(name)
+    }
+
+    pub fn combat(&self) -> &RulesEvaluator {
+        // Presence checked when loading
+        &self.rules["combat"]
+    }
+
+    pub fn get_skill(&self, name: &str) -> Option<&Skill> {
+        self.skills.get(name)
+    }
+
+    pub fn same_sources(&self, other: &AaribaScripts) -> bool {
+        self.sources == other.sources && self.skill_sources == other.skill_sources
     }
 }
 
 #[derive(Clone)]
 pub struct BehaviourTrees {
     inner: HashMap<String, BehaviourTreeFactory>,
+    // Content of the file each tree was defined in, for debugging
+    sources: HashMap<String, String>,
+    // File each tree was defined in
+    files: HashMap<String, String>,
 }
 
 impl BehaviourTrees {
-    // TODO: An append command
-    pub fn get_from_url(base_url: &str) -> Result<BehaviourTrees,Error> {
-        let mut url = String::from(base_url);
-        url.push_str("/zombie.bt");
-        debug!("Getting file {}", url);
-        let script = try!(utils::get_file_from_url(&url));
-        let mut map = HashMap::new();
+    pub fn load(source: &ConfigurationSource, manifest: &ScriptManifest) -> Result<BehaviourTrees,Error> {
+        let mut trees = BehaviourTrees {
+            inner: HashMap::new(),
+            sources: HashMap::new(),
+            files: HashMap::new(),
+        };
         let leaves = LeavesCollection::standard();
+        for file in manifest.behaviour_trees.iter() {
+            let script = try!(get_file(source, file));
+            try!(trees.append(file, &script, &leaves));
+        }
+        Ok(trees)
+    }
+
+    // Parses all the trees defined in the script, and adds them to the collection
+    fn append(&mut self, file: &str, script: &str, leaves: &LeavesCollection) -> Result<(),Error> {
+        leaves.reset_index();
         let parsed_trees =
-            try!(behaviour_tree::parse(&script,&leaves)
-                .map_err(Error::BehaviourTreeParsing));
+            try!(behaviour_tree::parse(script,leaves)
+                .map_err(|e| Error::BehaviourTreeParsing(format!("{}: {}", file, e))));
         for tree in parsed_trees {
             let name = String::from(tree.get_name());
-            map.insert(name,tree);
+            if let Some(first_file) = self.files.get(&name) {
+                return Err(Error::DuplicateTree {
+                    name: name.clone(),
+                    first_file: first_file.clone(),
+                    second_file: String::from(file),
+                });
+            }
+            self.sources.insert(name.clone(), String::from(script));
+            self.files.insert(name.clone(), String::from(file));
+            self.inner.insert(name,tree);
         }
-        let trees = BehaviourTrees {
-            inner: map,
-        };
-
-        Ok(trees)
+        Ok(())
     }
 
     pub fn generate_factory(&self, name: &str) -> Option<BehaviourTreeFactory> {
@@ -84,4 +215,48 @@ impl BehaviourTrees {
     pub fn generate_tree(&self, name: &str) -> Option<BehaviourTree> {
         self.inner.get(name).map(|f| f.optimize())
     }
+
+    pub fn get_source(&self, name: &str) -> Option<&str> {
+        self.sources.get(name).map(|s| s.as_str())
+    }
+
+    pub fn same_sources(&self, other: &BehaviourTrees) -> bool {
+        self.sources == other.sources
+    }
+
+    /// Names of the trees defined here, but not in `other`
+    pub fn missing_from(&self, other: &BehaviourTrees) -> Vec<String> {
+        self.inner.keys()
+            .filter(|name| !other.inner.contains_key(*name))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use ai::LeavesCollection;
+    use super::{BehaviourTrees,Error};
+
+    #[test]
+    fn duplicate_tree_names() {
+        let mut trees = BehaviourTrees {
+            inner: HashMap::new(),
+            sources: HashMap::new(),
+            files: HashMap::new(),
+        };
+        let leaves = LeavesCollection::standard();
+        trees.append("first.bt", "tree zombie { print_text(\"BRAINS\") }", &leaves).unwrap();
+        trees.append("other.bt", "tree skeleton { print_text(\"BONES\") }", &leaves).unwrap();
+        match trees.append("second.bt", "tree zombie { wander(5) }", &leaves) {
+            Err(Error::DuplicateTree { name, first_file, second_file }) => {
+                assert_eq!(name, "zombie");
+                assert_eq!(first_file, "first.bt");
+                assert_eq!(second_file, "second.bt");
+            }
+            other => panic!("Expected a duplicate tree error, found {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,18 @@
+// Entities and stores shared by the unit tests of the modules
+use uuid::Uuid;
+
+use id::Id;
+use entity::{Entity,EntityStore};
+
+/// A monster without AI at the given position, with a new id
+pub fn monster(x: f32, y: f32) -> Entity {
+    Entity::fake_ai(Id::forge(Uuid::new_v4()), x, y)
+}
+
+pub fn store(entities: Vec<Entity>) -> EntityStore {
+    let mut store = EntityStore::new();
+    for entity in entities {
+        store.push(entity);
+    }
+    store
+}
@@ -0,0 +1,193 @@
+// Test harness running an instance in the test thread
+//
+// Players are controlled by fake actors, whose orders are scripted by tick number. Ticks are
+// calculated one at a time, and all the notifications are kept to be checked by the tests
+use std::sync::mpsc::Receiver;
+use std::path::Path;
+
+use uuid::Uuid;
+
+use id::Id;
+use actor::{NetworkActor,ActorId};
+use entity::{Entity,StatusKind};
+use messages::{Command,Notification,Request,EntityOrder,Order};
+use instance::Instance;
+use scripts::{self,AaribaScripts,BehaviourTrees};
+use utils::ConfigurationSource;
+use data::{Player,Position,Stats,SpawnMonster,UNIQUE_MAP};
+
+pub static TICK_DURATION: f32 = 0.05;
+
+pub struct TestInstance {
+    instance: Instance,
+    requests: Receiver<Request>,
+    // Orders given before the calculation of the tick
+    scripted: Vec<(u64, ActorId, EntityOrder)>,
+    // Notifications of all the ticks, with their tick number
+    notifications: Vec<(u64, Notification)>,
+}
+
+#[derive(Debug,Clone,Copy)]
+pub struct TestPlayer {
+    pub actor: ActorId,
+    pub entity: Id<Entity>,
+}
+
+impl TestInstance {
+    /// An instance on the unique map, with the scripts of the repository
+    pub fn new(seed: u64) -> TestInstance {
+        let (scripts, trees) = load_scripts();
+        let (instance, requests) = Instance::headless(scripts, trees, &UNIQUE_MAP, seed, TICK_DURATION);
+        TestInstance::with_instance(instance, requests)
+    }
+
+    /// Same as `new`, the instance is recorded in the directory
+    pub fn recorded(seed: u64, directory: &Path) -> TestInstance {
+        let (scripts, trees) = load_scripts();
+        let (instance, requests) = Instance::recorded_headless(scripts,
+                                                               trees,
+                                                               &UNIQUE_MAP,
+                                                               seed,
+                                                               TICK_DURATION,
+                                                               directory.to_path_buf());
+        TestInstance::with_instance(instance, requests)
+    }
+
+    fn with_instance(instance: Instance, requests: Receiver<Request>) -> TestInstance {
+        TestInstance {
+            instance: instance,
+            requests: requests,
+            scripted: Vec::new(),
+            notifications: Vec::new(),
+        }
+    }
+
+    pub fn add_player(&mut self, x: f32, y: f32) -> TestPlayer {
+        let entity = Entity::from(test_player(x, y));
+        let id = entity.get_id();
+        let mut actor = NetworkActor::replayed(Id::new());
+        let actor_id = actor.get_id();
+        actor.register_entity(id);
+        self.instance.receive(Command::NewClient(actor, vec![entity]));
+        TestPlayer {
+            actor: actor_id,
+            entity: id,
+        }
+    }
+
+    pub fn spawn_monster(&mut self, x: f32, y: f32) -> Id<Entity> {
+        let monster = SpawnMonster {
+            monster_class: Id::forge(Uuid::new_v4()),
+            x: x,
+            y: y,
+            waypoints: Vec::new(),
+            think_period: None,
+        };
+        self.instance.spawn_monster(monster).id
+    }
+
+    /// The order will be executed during the given tick
+    pub fn order_at(&mut self, tick: u64, player: TestPlayer, order: Order) {
+        let order = EntityOrder {
+            entity: player.entity.as_u64(),
+            order: order,
+        };
+        self.scripted.push((tick, player.actor, order));
+    }
+
+    /// The order will be executed during the next tick
+    pub fn order(&mut self, player: TestPlayer, order: Order) {
+        let next = self.instance.get_tick() + 1;
+        self.order_at(next, player, order);
+    }
+
+    /// Calculates one tick, and returns its notifications
+    pub fn step(&mut self) -> Vec<Notification> {
+        let next = self.instance.get_tick() + 1;
+        let mut remaining = Vec::new();
+        for (tick, actor, order) in self.scripted.drain(..) {
+            if tick <= next {
+                assert!(self.instance.give_order(actor, order), "missing actor {}", actor);
+            } else {
+                remaining.push((tick, actor, order));
+            }
+        }
+        self.scripted = remaining;
+        let notifications = self.instance.step().to_vec();
+        for notification in notifications.iter() {
+            self.notifications.push((next, notification.clone()));
+        }
+        notifications
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+
+    pub fn id(&self) -> Id<Instance> {
+        self.instance.get_id()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.instance.get_tick()
+    }
+
+    pub fn entity(&self, id: Id<Entity>) -> Option<&Entity> {
+        self.instance.get_entity(id.into())
+    }
+
+    /// Stuns the entity right away, before the orders of the next tick are executed
+    pub fn stun(&mut self, id: Id<Entity>, duration: f32) {
+        self.instance.get_entity_mut(id.into()).unwrap().add_status(StatusKind::Stunned, duration);
+    }
+
+    pub fn notifications(&self) -> &[(u64, Notification)] {
+        &self.notifications
+    }
+
+    /// The requests sent to the Game since the last call
+    pub fn requests(&self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        while let Ok(request) = self.requests.try_recv() {
+            requests.push(request);
+        }
+        requests
+    }
+}
+
+/// The scripts of the repository
+pub fn load_scripts() -> (AaribaScripts, BehaviourTrees) {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts");
+    scripts::load_scripts(&ConfigurationSource::new(directory)).unwrap()
+}
+
+fn test_player(x: f32, y: f32) -> Player {
+    let stats = Stats {
+        level:          1,
+        strength:       2,
+        dexterity:      3,
+        constitution:   4,
+        intelligence:   5,
+        precision:      6,
+        wisdom:         7,
+        resistances:    Default::default(),
+    };
+    let position = Position {
+        x: x,
+        y: y,
+        map: UNIQUE_MAP.get_id(),
+    };
+    Player {
+        id:         Id::forge(Uuid::new_v4()),
+        name:       String::from("Test player"),
+        skin:       0,
+        current_pv: 100,
+        position:   position,
+        experience: 0,
+        gold:       0,
+        guild:      String::new(),
+        stats:      stats,
+    }
+}
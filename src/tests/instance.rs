@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self,File};
+use std::io::Read;
+
+use uuid::Uuid;
+
+use messages::{Notification,Order,Direction,NetworkCastTarget};
+use instance::{recording,replay};
+
+use super::harness::{self,TestInstance};
+
+#[test]
+fn walking_player_moves() {
+    let mut instance = TestInstance::new(42);
+    let player = instance.add_player(0.0, 0.0);
+    instance.order(player, Order::Walk(Some(Direction::East)));
+    instance.run(20);
+    let position = instance.entity(player.entity).unwrap().get_position();
+    assert!(position.x > 0.0);
+    assert_eq!(position.y, 0.0);
+
+    instance.order(player, Order::Walk(None));
+    instance.step();
+    let stopped = instance.entity(player.entity).unwrap().get_position();
+    instance.run(5);
+    assert_eq!(instance.entity(player.entity).unwrap().get_position(), stopped);
+}
+
+#[test]
+fn say_is_broadcast() {
+    let mut instance = TestInstance::new(42);
+    let player = instance.add_player(0.0, 0.0);
+    instance.order(player, Order::Say(String::from("hello")));
+    let notifications = instance.step();
+    assert!(notifications.iter().any(|n| {
+        match *n {
+            Notification::Say { entity, ref message } => {
+                entity == player.entity.as_u64() && message == "hello"
+            }
+            _ => false,
+        }
+    }));
+}
+
+#[test]
+fn attack_damages_monster() {
+    let mut instance = TestInstance::new(42);
+    let player = instance.add_player(0.0, 0.0);
+    // Players face east when they arrive, the attack box is in front of them
+    let monster = instance.spawn_monster(0.75, 0.0);
+    instance.order(player, Order::Attack);
+    let notifications = instance.step();
+    let damage = notifications.iter()
+        .filter_map(|n| {
+            match *n {
+                Notification::Damage { source, victim, amount, .. }
+                if source == player.entity.as_u64() && victim == monster.as_u64() => Some(amount),
+                _ => None,
+            }
+        })
+        .next()
+        .expect("the monster was not hit");
+    assert!(damage > 0);
+    assert_eq!(instance.entity(monster).unwrap().get_pv(), 100 - damage);
+}
+
+#[test]
+fn stunned_player_does_not_attack() {
+    let mut instance = TestInstance::new(42);
+    let player = instance.add_player(0.0, 0.0);
+    let monster = instance.spawn_monster(0.75, 0.0);
+    instance.order(player, Order::Attack);
+    // The order is valid when it is executed, the stun only takes effect during the tick
+    instance.stun(player.entity, 1.0);
+    let notifications = instance.step();
+    assert!(!notifications.iter().any(|n| {
+        match *n {
+            Notification::Damage { source, .. } => source == player.entity.as_u64(),
+            _ => false,
+        }
+    }));
+    assert_eq!(instance.entity(monster).unwrap().get_pv(), 100);
+}
+
+#[test]
+fn attacking_player_keeps_walking() {
+    let mut instance = TestInstance::new(42);
+    let player = instance.add_player(0.0, 0.0);
+    instance.order(player, Order::Walk(Some(Direction::East)));
+    instance.step();
+    instance.order(player, Order::Attack);
+    instance.step();
+    let attacked = instance.entity(player.entity).unwrap().get_position();
+    instance.run(5);
+    assert!(instance.entity(player.entity).unwrap().get_position().x > attacked.x);
+}
+
+// Entity ids come from a global counter, they are replaced by their order of appearance
+fn normalized(notifications: &[(u64, Notification)]) -> Vec<(u64, String)> {
+    let mut ids = HashMap::new();
+    let mut id = |entity: u64| {
+        let next = ids.len() as u64;
+        *ids.entry(entity).or_insert(next)
+    };
+    notifications.iter().map(|&(tick, ref notification)| {
+        let notification = match notification.clone() {
+            Notification::Walk { entity, orientation } => Notification::walk(id(entity), orientation),
+            Notification::Say { entity, message } => Notification::say(id(entity), message),
+            Notification::Position { entity, position, speed, pv } => {
+                Notification::position(id(entity), position, speed, pv)
+            }
+            Notification::StateUpdate { entity, position, speed, pv } => {
+                Notification::StateUpdate { entity: id(entity), position: position, speed: speed, pv: pv }
+            }
+            Notification::ThisIsYou { entity } => Notification::this_is_you(id(entity)),
+            Notification::NewEntity { entity, position, skin, pv } => {
+                Notification::new_entity(id(entity), position, skin, pv)
+            }
+            Notification::EntityHasQuit { entity } => Notification::entity_has_quit(id(entity)),
+            Notification::Damage { source, victim, amount, outcome, damage_type } => {
+                let source = id(source);
+                Notification::Damage {
+                    source: source,
+                    victim: id(victim),
+                    amount: amount,
+                    outcome: outcome,
+                    damage_type: damage_type,
+                }
+            }
+            Notification::Death { entity } => Notification::Death { entity: id(entity) },
+            Notification::CastStarted { entity, skill, duration } => {
+                Notification::CastStarted { entity: id(entity), skill: skill, duration: duration }
+            }
+            Notification::CastInterrupted { entity, skill } => {
+                Notification::CastInterrupted { entity: id(entity), skill: skill }
+            }
+            Notification::ProjectileSpawned { projectile, owner, position, velocity } => {
+                Notification::ProjectileSpawned {
+                    projectile: projectile,
+                    owner: id(owner),
+                    position: position,
+                    velocity: velocity,
+                }
+            }
+            other @ Notification::ProjectileDespawned { .. } => other,
+        };
+        (tick, format!("{:?}", notification))
+    }).collect()
+}
+
+// Notifications of a fight, with attacks and fireballs whose damage is random
+fn simulate(seed: u64) -> Vec<(u64, String)> {
+    let mut instance = TestInstance::new(seed);
+    let player = instance.add_player(0.0, 0.0);
+    instance.spawn_monster(3.0, 0.0);
+    for tick in 1..50 {
+        if tick % 5 == 0 {
+            instance.order_at(tick, player, Order::Attack);
+        }
+        if tick % 20 == 1 {
+            instance.order_at(tick, player, Order::Cast {
+                skill: String::from("fireball"),
+                target: NetworkCastTarget::Direction(Direction::East),
+            });
+        }
+    }
+    instance.run(100);
+    normalized(instance.notifications())
+}
+
+#[test]
+fn same_seed_same_simulation() {
+    let notifications = simulate(7);
+    assert!(notifications.iter().any(|&(_, ref n)| n.starts_with("Damage")));
+    assert!(notifications.iter().any(|&(_, ref n)| n.starts_with("ProjectileSpawned")));
+    assert_eq!(notifications, simulate(7));
+}
+
+#[test]
+fn walking_interrupts_cast() {
+    let mut instance = TestInstance::new(42);
+    let player = instance.add_player(0.0, 0.0);
+    let me = player.entity.as_u64();
+    instance.order(player, Order::Cast {
+        skill: String::from("fireball"),
+        target: NetworkCastTarget::Direction(Direction::East),
+    });
+    let notifications = instance.step();
+    assert!(notifications.iter().any(|n| {
+        match *n {
+            Notification::CastStarted { entity, ref skill, .. } => entity == me && skill == "fireball",
+            _ => false,
+        }
+    }));
+
+    instance.order(player, Order::Walk(Some(Direction::West)));
+    let notifications = instance.step();
+    assert!(notifications.iter().any(|n| {
+        match *n {
+            Notification::CastInterrupted { entity, ref skill } => entity == me && skill == "fireball",
+            _ => false,
+        }
+    }));
+    assert!(instance.entity(player.entity).unwrap().get_position().x < 0.0);
+}
+
+#[test]
+fn replay_reproduces_the_recording() {
+    let directory = env::temp_dir().join(format!("lycan-test-{}", Uuid::new_v4()));
+    fs::create_dir_all(&directory).unwrap();
+    let (id, ticks) = {
+        let mut instance = TestInstance::recorded(42, &directory);
+        let player = instance.add_player(0.0, 0.0);
+        instance.spawn_monster(3.0, 0.0);
+        instance.order_at(2, player, Order::Say(String::from("hello")));
+        instance.order_at(3, player, Order::Cast {
+            skill: String::from("fireball"),
+            target: NetworkCastTarget::Direction(Direction::East),
+        });
+        instance.order_at(30, player, Order::Walk(Some(Direction::East)));
+        for tick in 35..45 {
+            instance.order_at(tick, player, Order::Attack);
+        }
+        instance.order_at(45, player, Order::Walk(None));
+        instance.run(60);
+        (instance.id(), instance.tick())
+    };
+
+    let recorded = recording::read_recording(&recording::recording_path(&directory, id)).unwrap();
+    let (scripts, trees) = harness::load_scripts();
+    let mut replayed = Vec::new();
+    replay::replay(recorded, scripts, trees, Some(ticks), &mut replayed).unwrap();
+    let mut original = String::new();
+    File::open(recording::notifications_path(&directory, id)).unwrap()
+        .read_to_string(&mut original).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    let original: Vec<_> = original.lines().collect();
+    let replayed = String::from_utf8(replayed).unwrap();
+    let replayed: Vec<_> = replayed.lines().collect();
+    assert!(original.iter().any(|line| line.contains("Damage")));
+    // Each line starts with its tick
+    assert_eq!(original.last().unwrap().split(' ').next(), Some(&*ticks.to_string()));
+    for (original, replayed) in original.iter().zip(replayed.iter()) {
+        assert_eq!(original, replayed);
+    }
+    assert_eq!(original.len(), replayed.len());
+}
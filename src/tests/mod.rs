@@ -1 +1,5 @@
 pub mod util;
+pub mod harness;
+pub mod fixtures;
+
+mod instance;
@@ -4,10 +4,10 @@ use std::thread;
 pub fn create_connection() -> (TcpStream,TcpStream) {
     let server = TcpListener::bind("localhost:0").unwrap();
     let port = server.local_addr().unwrap().port();
-    let guard = thread::scoped(|| {
+    let guard = thread::spawn(move || {
         TcpStream::connect(("localhost",port)).unwrap()
     });
     let (server_socket, _) = server.accept().unwrap();
-    let client_socket = guard.join();
+    let client_socket = guard.join().unwrap();
     (server_socket, client_socket)
 }
@@ -1,6 +1,7 @@
-use std::io::{Read,Write};
+use std::io::{self,Read,Write};
+use std::fmt;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path,PathBuf};
 
 use hyper::Client;
 use hyper::error::Error as HyperError;
@@ -8,6 +9,84 @@ use serde::{Serialize};
 use serde_json;
 
 
+/// Location of the configuration files (scripts, maps, players ...)
+///
+/// They can either be served by a HTTP server, or read from a local directory
+#[derive(Debug,Clone,PartialEq)]
+pub enum ConfigurationSource {
+    Http(String),
+    Directory(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Hyper(HyperError),
+    Io(io::Error),
+}
+
+impl From<HyperError> for LoadError {
+    fn from(e: HyperError) -> LoadError {
+        LoadError::Hyper(e)
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            LoadError::Hyper(ref e) => write!(f, "{}", e),
+            LoadError::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ConfigurationSource {
+    /// Accepts http:// and https:// URLs, file:// URLs or a plain path to a directory
+    pub fn new(location: &str) -> ConfigurationSource {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            ConfigurationSource::Http(location.trim_right_matches('/').to_string())
+        } else if location.starts_with("file://") {
+            ConfigurationSource::Directory(PathBuf::from(&location["file://".len()..]))
+        } else {
+            ConfigurationSource::Directory(PathBuf::from(location))
+        }
+    }
+
+    /// Gets the content of a file, given its path relative to the configuration root
+    pub fn get_file(&self, path: &str) -> Result<String,LoadError> {
+        match *self {
+            ConfigurationSource::Http(ref base) => {
+                let url = format!("{}/{}", base, path);
+                debug!("Getting file {}", url);
+                let content = try!(get_file_from_url(&url));
+                Ok(content)
+            }
+            ConfigurationSource::Directory(ref base) => {
+                let path = base.join(path);
+                debug!("Reading file {}", path.display());
+                let mut content = String::new();
+                let mut file = try!(File::open(&path));
+                try!(file.read_to_string(&mut content));
+                Ok(content)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigurationSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(),fmt::Error> {
+        match *self {
+            ConfigurationSource::Http(ref base) => write!(f, "{}", base),
+            ConfigurationSource::Directory(ref base) => write!(f, "{}", base.display()),
+        }
+    }
+}
+
 pub fn get_file_from_url(url: &str) -> Result<String,HyperError> {
     let client = Client::new();
     let mut response = try!(client.get(url).send());
@@ -32,3 +111,20 @@ where T: Serialize {
     let mut file = try!(File::create(file));
     serde_json::to_writer_pretty(&mut file, s)
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::ConfigurationSource;
+
+    #[test]
+    fn configuration_source() {
+        assert_eq!(ConfigurationSource::new("http://localhost:9000/"),
+                   ConfigurationSource::Http("http://localhost:9000".to_string()));
+        assert_eq!(ConfigurationSource::new("file:///etc/lycan"),
+                   ConfigurationSource::Directory(PathBuf::from("/etc/lycan")));
+        assert_eq!(ConfigurationSource::new("scripts"),
+                   ConfigurationSource::Directory(PathBuf::from("scripts")));
+    }
+}